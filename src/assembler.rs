@@ -0,0 +1,609 @@
+use crate::chip8::PROGRAM_START;
+use crate::instruction::Instruction;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+// how deep INCLUDEs (and constants defined by other constants) can nest, stops cycles
+const MAX_DEPTH: usize = 16;
+
+// source line the error was found on, numbered from 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AssemblyError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for AssemblyError {}
+
+#[derive(Clone, Debug)]
+struct Location {
+    file: String,
+    line: usize,
+}
+
+impl Location {
+    fn error(&self, message: String) -> AssemblyError {
+        AssemblyError {
+            file: self.file.clone(),
+            line: self.line,
+            message,
+        }
+    }
+}
+
+// source line with comments removed and includes expanded
+struct Line {
+    location: Location,
+    text: String,
+}
+
+enum Statement {
+    Instruction {
+        mnemonic: String,
+        operands: Vec<String>,
+    },
+    Bytes(Vec<String>),
+    Words(Vec<String>),
+}
+
+impl Statement {
+    fn size(&self) -> usize {
+        match self {
+            Statement::Instruction { mnemonic, operands } => {
+                // LD I, LONG NNNN is the only 4 bytes instruction
+                let long = mnemonic == "LD"
+                    && operands.len() == 2
+                    && operands[1].to_ascii_uppercase().starts_with("LONG ");
+                if long {
+                    4
+                } else {
+                    2
+                }
+            }
+            Statement::Bytes(values) => values.len(),
+            Statement::Words(values) => values.len() * 2,
+        }
+    }
+}
+
+enum Operand<'a> {
+    Register(u8),
+    I,
+    IndirectI,
+    DelayTimer,
+    SoundTimer,
+    Key,
+    Font,
+    BigFont,
+    Bcd,
+    Flags,
+    Long(&'a str),
+    Value(&'a str),
+}
+
+impl<'a> Operand<'a> {
+    // keywords take precedence, a label called `B` can't be used as an operand
+    fn parse(text: &'a str) -> Self {
+        let upper = text.to_ascii_uppercase();
+        match upper.as_str() {
+            "I" => Operand::I,
+            "[I]" => Operand::IndirectI,
+            "DT" => Operand::DelayTimer,
+            "ST" => Operand::SoundTimer,
+            "K" => Operand::Key,
+            "F" => Operand::Font,
+            "HF" => Operand::BigFont,
+            "B" => Operand::Bcd,
+            "R" => Operand::Flags,
+            _ if upper.len() == 2 && upper.starts_with('V') => {
+                match u8::from_str_radix(&upper[1..], 16) {
+                    Ok(register) => Operand::Register(register),
+                    Err(_) => Operand::Value(text),
+                }
+            }
+            _ if upper.starts_with("LONG ") => Operand::Long(text[5..].trim()),
+            _ => Operand::Value(text),
+        }
+    }
+}
+
+fn is_symbol(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (digits, radix) = if let Some(hex) = text
+        .strip_prefix('#')
+        .or_else(|| text.strip_prefix('$'))
+        .or_else(|| text.strip_prefix("0x"))
+        .or_else(|| text.strip_prefix("0X"))
+    {
+        (hex, 16)
+    } else if let Some(binary) = text
+        .strip_prefix('%')
+        .or_else(|| text.strip_prefix("0b"))
+        .or_else(|| text.strip_prefix("0B"))
+    {
+        (binary, 2)
+    } else {
+        (text, 10)
+    };
+
+    // underscores can be used to group digits, e.g. %1111_0000
+    i64::from_str_radix(&digits.replace('_', ""), radix).ok()
+}
+
+/*
+ * Two pass assembler for the syntax the disassembler produces (Cowgod's mnemonics):
+ *
+ *     SPEED EQU 3           ; constant, can refer to other constants and labels
+ *     INCLUDE "sprites.asm" ; path relative to the including file
+ *     loop:                 ; label, can also precede an instruction on the same line
+ *         ADD V0, SPEED
+ *         JP loop
+ *     data: DB #3C, %0100_0010, 60
+ *         DW label + 2
+ *
+ * Numbers are decimal, hex (#FF, $FF, 0xFF) or binary (%1010, 0b1010),
+ * values can be added and subtracted. The ROM is assembled for address 0x200.
+ */
+#[derive(Default)]
+struct Assembler {
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, (String, Location)>,
+}
+
+impl Assembler {
+    fn read_lines(
+        &self,
+        source: &str,
+        file: &str,
+        directory: &Path,
+        depth: usize,
+        lines: &mut Vec<Line>,
+    ) -> Result<(), AssemblyError> {
+        for (index, text) in source.lines().enumerate() {
+            let location = Location {
+                file: file.to_string(),
+                line: index + 1,
+            };
+            let text = match text.find(';') {
+                Some(comment) => &text[..comment],
+                None => text,
+            }
+            .trim();
+
+            let include = text
+                .split_once(char::is_whitespace)
+                .filter(|(directive, _)| directive.eq_ignore_ascii_case("INCLUDE"));
+            if let Some((_, path)) = include {
+                let path = path.trim().trim_matches('"');
+                if depth == MAX_DEPTH {
+                    return Err(location.error(format!("`{}` is included too deep", path)));
+                }
+
+                let path = directory.join(path);
+                let source = fs::read_to_string(&path).map_err(|error| {
+                    location.error(format!("can't include `{}`: {}", path.display(), error))
+                })?;
+                let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+                self.read_lines(
+                    &source,
+                    &path.display().to_string(),
+                    &directory,
+                    depth + 1,
+                    lines,
+                )?;
+            } else if !text.is_empty() {
+                lines.push(Line {
+                    location,
+                    text: text.to_string(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    fn define(&self, name: &str, location: &Location) -> Result<(), AssemblyError> {
+        if !is_symbol(name) {
+            return Err(location.error(format!("invalid name `{}`", name)));
+        }
+        if self.labels.contains_key(name) || self.constants.contains_key(name) {
+            return Err(location.error(format!("`{}` is already defined", name)));
+        }
+        Ok(())
+    }
+
+    // first pass, collects labels and constants and sizes every statement
+    fn parse(&mut self, lines: Vec<Line>) -> Result<Vec<(Location, Statement)>, AssemblyError> {
+        let mut statements = Vec::new();
+        let mut address = PROGRAM_START;
+
+        for line in lines {
+            let mut text = line.text.as_str();
+
+            while let Some((label, rest)) = text.split_once(':') {
+                let label = label.trim();
+                self.define(label, &line.location)?;
+                self.labels.insert(label.to_string(), address);
+                text = rest.trim();
+            }
+            if text.is_empty() {
+                continue;
+            }
+
+            let (first, rest) = text
+                .split_once(char::is_whitespace)
+                .map(|(first, rest)| (first, rest.trim()))
+                .unwrap_or((text, ""));
+
+            if let Some((directive, value)) = rest.split_once(char::is_whitespace) {
+                if directive.eq_ignore_ascii_case("EQU") {
+                    self.define(first, &line.location)?;
+                    self.constants.insert(
+                        first.to_string(),
+                        (value.trim().to_string(), line.location.clone()),
+                    );
+                    continue;
+                }
+            }
+
+            let operands: Vec<String> = if rest.is_empty() {
+                Vec::new()
+            } else {
+                rest.split(',')
+                    .map(|operand| operand.trim().to_string())
+                    .collect()
+            };
+            let mnemonic = first.to_ascii_uppercase();
+            let statement = match mnemonic.as_str() {
+                "DB" => Statement::Bytes(operands),
+                "DW" => Statement::Words(operands),
+                _ => Statement::Instruction { mnemonic, operands },
+            };
+
+            address += statement.size();
+            statements.push((line.location, statement));
+        }
+
+        Ok(statements)
+    }
+
+    fn evaluate(&self, expression: &str, depth: usize) -> Result<i64, String> {
+        let mut value = 0;
+        let mut sign = 1;
+        let mut term = String::new();
+
+        for c in expression.chars() {
+            if c == '+' || c == '-' {
+                if term.trim().is_empty() {
+                    // unary sign
+                    if c == '-' {
+                        sign = -sign;
+                    }
+                } else {
+                    value += sign * self.term(term.trim(), depth)?;
+                    term.clear();
+                    sign = if c == '-' { -1 } else { 1 };
+                }
+            } else {
+                term.push(c);
+            }
+        }
+
+        if term.trim().is_empty() {
+            return Err(format!("expected a value in `{}`", expression));
+        }
+        Ok(value + sign * self.term(term.trim(), depth)?)
+    }
+
+    fn term(&self, term: &str, depth: usize) -> Result<i64, String> {
+        if let Some(number) = parse_number(term) {
+            return Ok(number);
+        }
+
+        if let Some(address) = self.labels.get(term) {
+            Ok(*address as i64)
+        } else if let Some((expression, _)) = self.constants.get(term) {
+            if depth == MAX_DEPTH {
+                return Err(format!("`{}` is defined in terms of itself", term));
+            }
+            self.evaluate(expression, depth + 1)
+        } else if is_symbol(term) {
+            Err(format!("undefined symbol `{}`", term))
+        } else {
+            Err(format!("invalid value `{}`", term))
+        }
+    }
+
+    // value which has to fit in `bits`, negative values are allowed for bytes and words
+    fn value(&self, expression: &str, bits: u32) -> Result<u16, String> {
+        let value = self.evaluate(expression, 0)?;
+        let max = (1 << bits) - 1;
+        let min = if bits == 8 || bits == 16 {
+            -(1 << (bits - 1))
+        } else {
+            0
+        };
+        if value < min || value > max {
+            return Err(format!(
+                "`{}` = {} does not fit in {} bits",
+                expression, value, bits
+            ));
+        }
+        Ok((value & max) as u16)
+    }
+
+    fn nibble(&self, expression: &str) -> Result<u8, String> {
+        self.value(expression, 4).map(|value| value as u8)
+    }
+
+    fn byte(&self, expression: &str) -> Result<u8, String> {
+        self.value(expression, 8).map(|value| value as u8)
+    }
+
+    fn address(&self, expression: &str) -> Result<u16, String> {
+        self.value(expression, 12)
+    }
+
+    fn instruction(&self, mnemonic: &str, operands: &[String]) -> Result<Vec<u8>, String> {
+        use Operand::*;
+
+        let parsed: Vec<Operand> = operands.iter().map(|text| Operand::parse(text)).collect();
+        let mut long_address = None;
+
+        let instruction = match (mnemonic, parsed.as_slice()) {
+            ("CLS", []) => Instruction::Clear,
+            ("RET", []) => Instruction::Return,
+            ("SCD", [Value(n)]) => Instruction::ScrollDown(self.nibble(n)?),
+            ("SCU", [Value(n)]) => Instruction::ScrollUp(self.nibble(n)?),
+            ("SCR", []) => Instruction::ScrollRight,
+            ("SCL", []) => Instruction::ScrollLeft,
+            ("EXIT", []) => Instruction::Exit,
+            ("LOW", []) => Instruction::LowRes,
+            ("HIGH", []) => Instruction::HighRes,
+            ("SYS", [Value(nnn)]) => Instruction::System(self.address(nnn)?),
+            ("JP", [Value(nnn)]) => Instruction::Jump(self.address(nnn)?),
+            ("JP", [Register(0), Value(nnn)]) => {
+                let nnn = self.address(nnn)?;
+                Instruction::JumpWithOffset((nnn >> 8) as u8, nnn)
+            }
+            ("CALL", [Value(nnn)]) => Instruction::Call(self.address(nnn)?),
+            ("SE", [Register(x), Register(y)]) => Instruction::SkipIfEqual(*x, *y),
+            ("SE", [Register(x), Value(nn)]) => Instruction::SkipIfEqualByte(*x, self.byte(nn)?),
+            ("SNE", [Register(x), Register(y)]) => Instruction::SkipIfNotEqual(*x, *y),
+            ("SNE", [Register(x), Value(nn)]) => {
+                Instruction::SkipIfNotEqualByte(*x, self.byte(nn)?)
+            }
+            ("SAVE", [Register(x), Register(y)]) => Instruction::StoreRange(*x, *y),
+            ("LOAD", [Register(x), Register(y)]) => Instruction::LoadRange(*x, *y),
+            ("LD", [Register(x), Register(y)]) => Instruction::Set(*x, *y),
+            ("LD", [Register(x), Value(nn)]) => Instruction::SetByte(*x, self.byte(nn)?),
+            ("LD", [I, Value(nnn)]) => Instruction::SetI(self.address(nnn)?),
+            ("LD", [I, Long(nnnn)]) => {
+                long_address = Some(self.value(nnnn, 16)?);
+                Instruction::SetILong
+            }
+            ("LD", [Register(x), DelayTimer]) => Instruction::GetDelay(*x),
+            ("LD", [Register(x), Key]) => Instruction::WaitForKey(*x),
+            ("LD", [DelayTimer, Register(x)]) => Instruction::SetDelay(*x),
+            ("LD", [SoundTimer, Register(x)]) => Instruction::SetSound(*x),
+            ("LD", [Font, Register(x)]) => Instruction::Font(*x),
+            ("LD", [BigFont, Register(x)]) => Instruction::BigFont(*x),
+            ("LD", [Bcd, Register(x)]) => Instruction::Bcd(*x),
+            ("LD", [IndirectI, Register(x)]) => Instruction::Store(*x),
+            ("LD", [Register(x), IndirectI]) => Instruction::Load(*x),
+            ("LD", [Flags, Register(x)]) => Instruction::StoreFlags(*x),
+            ("LD", [Register(x), Flags]) => Instruction::LoadFlags(*x),
+            ("ADD", [Register(x), Register(y)]) => Instruction::Add(*x, *y),
+            ("ADD", [Register(x), Value(nn)]) => Instruction::AddByte(*x, self.byte(nn)?),
+            ("ADD", [I, Register(x)]) => Instruction::AddI(*x),
+            ("OR", [Register(x), Register(y)]) => Instruction::Or(*x, *y),
+            ("AND", [Register(x), Register(y)]) => Instruction::And(*x, *y),
+            ("XOR", [Register(x), Register(y)]) => Instruction::Xor(*x, *y),
+            ("SUB", [Register(x), Register(y)]) => Instruction::Sub(*x, *y),
+            ("SUBN", [Register(x), Register(y)]) => Instruction::SubReverse(*x, *y),
+            // without VY shifts VX, whether the shift quirk is enabled or not
+            ("SHR", [Register(x)]) => Instruction::ShiftRight(*x, *x),
+            ("SHR", [Register(x), Register(y)]) => Instruction::ShiftRight(*x, *y),
+            ("SHL", [Register(x)]) => Instruction::ShiftLeft(*x, *x),
+            ("SHL", [Register(x), Register(y)]) => Instruction::ShiftLeft(*x, *y),
+            ("RND", [Register(x), Value(nn)]) => Instruction::Random(*x, self.byte(nn)?),
+            ("DRW", [Register(x), Register(y), Value(n)]) => {
+                Instruction::Draw(*x, *y, self.nibble(n)?)
+            }
+            ("SKP", [Register(x)]) => Instruction::SkipIfKey(*x),
+            ("SKNP", [Register(x)]) => Instruction::SkipIfNotKey(*x),
+            ("PLANE", [Value(n)]) => Instruction::Planes(self.nibble(n)?),
+            ("AUDIO", []) => Instruction::Audio,
+            ("PITCH", [Register(x)]) => Instruction::Pitch(*x),
+            _ => {
+                return Err(format!(
+                    "invalid instruction `{} {}`",
+                    mnemonic,
+                    operands.join(", ")
+                ))
+            }
+        };
+
+        let mut bytes = instruction.encode().to_be_bytes().to_vec();
+        if let Some(nnnn) = long_address {
+            bytes.extend_from_slice(&nnnn.to_be_bytes());
+        }
+        Ok(bytes)
+    }
+
+    fn assemble(&mut self, lines: Vec<Line>) -> Result<Vec<u8>, AssemblyError> {
+        let statements = self.parse(lines)?;
+
+        // constants are only evaluated when used, make sure unused ones are valid as well
+        for (name, (_, location)) in &self.constants {
+            self.evaluate(name, 0)
+                .map_err(|message| location.error(message))?;
+        }
+
+        let mut rom = Vec::new();
+        for (location, statement) in statements {
+            let bytes = match statement {
+                Statement::Instruction { mnemonic, operands } => {
+                    self.instruction(&mnemonic, &operands)
+                }
+                Statement::Bytes(values) => values.iter().map(|value| self.byte(value)).collect(),
+                Statement::Words(values) => values
+                    .iter()
+                    .map(|value| self.value(value, 16).map(u16::to_be_bytes))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|words| words.concat()),
+            };
+            rom.extend(bytes.map_err(|message| location.error(message))?);
+        }
+
+        Ok(rom)
+    }
+}
+
+// includes are resolved relative to the current directory
+pub fn assemble(source: &str) -> Result<Vec<u8>, AssemblyError> {
+    let mut assembler = Assembler::default();
+    let mut lines = Vec::new();
+    assembler.read_lines(source, "<source>", Path::new(""), 0, &mut lines)?;
+    assembler.assemble(lines)
+}
+
+// includes are resolved relative to the directory of `path`
+pub fn assemble_file(path: &Path) -> Result<Vec<u8>, AssemblyError> {
+    let source = fs::read_to_string(path).map_err(|error| AssemblyError {
+        file: path.display().to_string(),
+        line: 0,
+        message: error.to_string(),
+    })?;
+    let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+    let mut assembler = Assembler::default();
+    let mut lines = Vec::new();
+    assembler.read_lines(
+        &source,
+        &path.display().to_string(),
+        &directory,
+        0,
+        &mut lines,
+    )?;
+    assembler.assemble(lines)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disassembler::disassemble;
+
+    #[test]
+    fn test_assemble_instructions() {
+        let source = "
+            CLS
+            LD V0, #0A       ; comment
+            ld v1, v0
+            LD I, #300
+            DRW V0, V1, 5
+            LD [I], VF
+            SHR V3
+            LD I, LONG #1234
+        ";
+
+        assert_eq!(
+            Ok(vec![
+                0x00, 0xE0, 0x60, 0x0A, 0x81, 0x00, 0xA3, 0x00, 0xD0, 0x15, 0xFF, 0x55, 0x83, 0x36,
+                0xF0, 0x00, 0x12, 0x34
+            ]),
+            assemble(source)
+        );
+    }
+
+    #[test]
+    fn test_assemble_labels_and_constants() {
+        let source = "
+            A EQU B + 1
+            B EQU A
+        ";
+        assert!(assemble(source).is_err());
+
+        let source = "
+            SIZE EQU 4
+            STEP EQU SIZE - 1
+            start:
+                ADD V0, STEP
+                CALL draw
+                JP start
+            draw: LD I, sprite + 1
+                RET
+            sprite:
+                DB %1111_0000, $90, 255
+                DW sprite, -1
+        ";
+
+        assert_eq!(
+            Ok(vec![
+                0x70, 0x03, 0x22, 0x06, 0x12, 0x00, 0xA2, 0x0B, 0x00, 0xEE, 0xF0, 0x90, 0xFF, 0x02,
+                0x0A, 0xFF, 0xFF
+            ]),
+            assemble(source)
+        );
+    }
+
+    #[test]
+    fn test_assemble_reports_line_numbers() {
+        let source = "CLS\nLD V0, 256\n";
+        assert_eq!(
+            Err(AssemblyError {
+                file: "<source>".to_string(),
+                line: 2,
+                message: "`256` = 256 does not fit in 8 bits".to_string(),
+            }),
+            assemble(source)
+        );
+
+        assert_eq!(3, assemble("\n\nJP nowhere").unwrap_err().line);
+        assert_eq!(1, assemble("MOV V0, V1").unwrap_err().line);
+        assert_eq!(2, assemble("a:\na: CLS").unwrap_err().line);
+    }
+
+    #[test]
+    fn test_assemble_include() {
+        let directory = std::env::temp_dir().join("chip-8-rs-assembler-test");
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("sprites.asm"), "sprite: DB #FF\n").unwrap();
+        fs::write(
+            directory.join("main.asm"),
+            "LD I, sprite\nINCLUDE \"sprites.asm\"\n",
+        )
+        .unwrap();
+
+        assert_eq!(
+            Ok(vec![0xA2, 0x02, 0xFF]),
+            assemble_file(&directory.join("main.asm"))
+        );
+    }
+
+    #[test]
+    fn test_disassembly_round_trip() {
+        let rom = [
+            0x22, 0x08, 0x12, 0x02, 0xFF, 0x3C, 0xF0, 0x00, 0xA2, 0x10, 0xD0, 0x15, 0xF0, 0x00,
+            0x02, 0x14, 0x00, 0xEE, 0xAA, 0xBB,
+        ];
+        assert_eq!(Ok(rom.to_vec()), assemble(&disassemble(&rom)));
+
+        // I points inside an instruction
+        let rom = [0xA2, 0x01, 0x00, 0xFD];
+        assert_eq!(Ok(rom.to_vec()), assemble(&disassemble(&rom)));
+    }
+}
@@ -0,0 +1,40 @@
+// raylib output for the beeper
+use chip_8_rs::beeper::{AudioSink, Beeper, SAMPLE_RATE, SILENCE};
+use chip_8_rs::AudioPattern;
+use raylib::prelude::*;
+
+// frames per audio stream buffer, ~23ms at 44.1 kHz
+const BUFFER_FRAMES: usize = 1024;
+
+pub struct RaylibSink<'aud> {
+    stream: AudioStream<'aud>,
+    beeper: Beeper,
+    buffer: Vec<u8>,
+}
+
+impl<'aud> RaylibSink<'aud> {
+    pub fn new(audio: &'aud RaylibAudio, beeper: Beeper) -> Self {
+        // the stream has to be created with buffers of the size we are going to push
+        unsafe {
+            raylib::ffi::SetAudioStreamBufferSizeDefault(BUFFER_FRAMES as i32);
+        }
+        // 8-bit samples, so that a sample is exactly one byte of the buffer
+        let mut stream = audio.new_audio_stream(SAMPLE_RATE, 8, 1);
+        stream.play();
+
+        RaylibSink {
+            stream,
+            beeper,
+            buffer: vec![SILENCE; BUFFER_FRAMES],
+        }
+    }
+}
+
+impl AudioSink for RaylibSink<'_> {
+    fn update(&mut self, playing: bool, pattern: Option<AudioPattern>) {
+        while self.stream.is_processed() {
+            self.beeper.fill(playing, pattern, &mut self.buffer);
+            self.stream.update(&self.buffer);
+        }
+    }
+}
@@ -0,0 +1,166 @@
+// beeper sound for the sound timer, frontends pass the samples to their audio device
+use crate::chip8::AudioPattern;
+use std::str::FromStr;
+
+pub const SAMPLE_RATE: u32 = 44100;
+pub const SILENCE: u8 = 128;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Waveform {
+    Square,
+    Triangle,
+    Sawtooth,
+    Sine,
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "square" => Ok(Waveform::Square),
+            "triangle" => Ok(Waveform::Triangle),
+            "sawtooth" => Ok(Waveform::Sawtooth),
+            "sine" => Ok(Waveform::Sine),
+            _ => Err(format!(
+                "unknown waveform `{}`, expected one of: square, triangle, sawtooth, sine",
+                name
+            )),
+        }
+    }
+}
+
+// tone generator, produces unsigned 8-bit mono samples
+// plays XO-CHIP audio pattern instead of the tone when program provides one
+pub struct Beeper {
+    frequency: f32,
+    volume: f32,
+    waveform: Waveform,
+    phase: f32,
+}
+
+impl Beeper {
+    pub fn new(frequency: f32, volume: f32, waveform: Waveform) -> Self {
+        Beeper {
+            frequency,
+            volume: volume.clamp(0.0, 1.0),
+            waveform,
+            phase: 0.0,
+        }
+    }
+
+    fn sample(&self) -> f32 {
+        match self.waveform {
+            Waveform::Square => {
+                if self.phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 4.0 * (self.phase - 0.5).abs() - 1.0,
+            Waveform::Sawtooth => 2.0 * self.phase - 1.0,
+            Waveform::Sine => (self.phase * std::f32::consts::TAU).sin(),
+        }
+    }
+
+    // phase covers the whole pattern, 128 bits
+    fn pattern_sample(&self, pattern: &AudioPattern) -> f32 {
+        let bit = (self.phase * 128.0) as usize;
+        if pattern.buffer[bit / 8] & (0x80 >> (bit % 8)) != 0 {
+            1.0
+        } else {
+            -1.0
+        }
+    }
+
+    // fill buffer with the tone (or pattern) while playing, silence otherwise
+    pub fn fill(&mut self, playing: bool, pattern: Option<AudioPattern>, buffer: &mut [u8]) {
+        if !playing {
+            self.phase = 0.0;
+            buffer.fill(SILENCE);
+            return;
+        }
+
+        let step = match &pattern {
+            Some(pattern) => pattern.playback_rate() / 128.0 / SAMPLE_RATE as f32,
+            None => self.frequency / SAMPLE_RATE as f32,
+        };
+        for sample in buffer.iter_mut() {
+            let value = match &pattern {
+                Some(pattern) => self.pattern_sample(pattern),
+                None => self.sample(),
+            };
+            *sample = (SILENCE as f32 + value * self.volume * 127.0) as u8;
+            self.phase = (self.phase + step).fract();
+        }
+    }
+}
+
+// where the beeper output goes, called once per rendered frame
+pub trait AudioSink {
+    fn update(&mut self, playing: bool, pattern: Option<AudioPattern>);
+}
+
+// discards all audio, used when there is no audio device or sound is disabled
+pub struct NullSink;
+
+impl AudioSink for NullSink {
+    fn update(&mut self, _playing: bool, _pattern: Option<AudioPattern>) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_beeper_is_silent_when_not_playing() {
+        let mut beeper = Beeper::new(440.0, 1.0, Waveform::Square);
+        let mut buffer = [0u8; 64];
+
+        beeper.fill(false, None, &mut buffer);
+        assert!(buffer.iter().all(|sample| *sample == SILENCE));
+    }
+
+    #[test]
+    fn test_beeper_square_wave() {
+        // 4 samples per period
+        let mut beeper = Beeper::new(SAMPLE_RATE as f32 / 4.0, 1.0, Waveform::Square);
+        let mut buffer = [0u8; 8];
+
+        beeper.fill(true, None, &mut buffer);
+        assert_eq!([255, 255, 1, 1, 255, 255, 1, 1], buffer);
+    }
+
+    #[test]
+    fn test_beeper_volume_scales_amplitude() {
+        let mut beeper = Beeper::new(SAMPLE_RATE as f32 / 4.0, 0.5, Waveform::Square);
+        let mut buffer = [0u8; 4];
+
+        beeper.fill(true, None, &mut buffer);
+        assert_eq!([191, 191, 64, 64], buffer);
+    }
+
+    #[test]
+    fn test_parse_waveform() {
+        assert_eq!(Ok(Waveform::Sawtooth), "sawtooth".parse());
+        assert!("noise".parse::<Waveform>().is_err());
+    }
+
+    #[test]
+    fn test_beeper_plays_audio_pattern() {
+        let mut beeper = Beeper::new(440.0, 1.0, Waveform::Square);
+        let mut buffer = [0u8; 1411]; // one pattern at 4000 samples per second
+        let mut pattern = AudioPattern {
+            buffer: [0; 16],
+            pitch: 64,
+        };
+        pattern.buffer[..8].fill(0xFF); // first half on, second half off
+
+        beeper.fill(true, Some(pattern), &mut buffer);
+        assert_eq!(255, buffer[0]);
+        assert_eq!(255, buffer[700]);
+        assert_eq!(1, buffer[710]);
+        assert_eq!(1, buffer[1410]);
+    }
+}
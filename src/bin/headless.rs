@@ -0,0 +1,387 @@
+// runs a ROM without a window, for CI and batch testing of ROMs
+// prints the final screen and registers, exits with 1 if the program faulted
+use chip_8_rs::{chip8, Chip8, Chip8Error, Movie, Quirks};
+use clap::Parser;
+use std::fs::File;
+use std::io::BufWriter;
+use std::process::ExitCode;
+
+#[derive(Parser, Debug)]
+struct Args {
+    /// ROM to run
+    #[arg(short, required = true)]
+    rom: String,
+
+    /// Number of 60 Hz frames to run
+    #[arg(long, default_value_t = 60)]
+    frames: u32,
+
+    /// Number of instructions to run, overrides --frames
+    #[arg(long, conflicts_with = "frames")]
+    instructions: Option<u64>,
+
+    /// CPU speed in instructions per second
+    #[arg(
+        long,
+        default_value_t = chip8::DEFAULT_CPU_HZ,
+        value_parser = clap::value_parser!(u32).range(1..=chip8::MAX_CPU_HZ as i64)
+    )]
+    cpu_hz: u32,
+
+    /// CPU speed in instructions per 60 Hz frame, overrides --cpu-hz
+    #[arg(
+        long,
+        conflicts_with = "cpu_hz",
+        value_parser = clap::value_parser!(u32).range(..=chip8::MAX_IPF as i64)
+    )]
+    ipf: Option<u32>,
+
+    /// Interpreter behavior to emulate: chip-8, chip-48, super-chip or xo-chip
+    #[arg(long, default_value = "chip-8")]
+    quirks: Quirks,
+
+    /// Seed for the random numbers of Cxnn
+    #[arg(long, default_value_t = chip8::DEFAULT_SEED)]
+    seed: u64,
+
+    /// Hold a key, FRAME:KEY[:FRAMES] e.g. 30:5:10 holds key 5 for 10 frames from frame 30
+    #[arg(long = "key", value_parser = parse_key_press)]
+    keys: Vec<KeyPress>,
+
+    /// Write the final screen to a PNG file
+    #[arg(long)]
+    png: Option<String>,
+
+    /// Size of a CHIP-8 pixel in the PNG
+    #[arg(long, default_value_t = 4)]
+    png_scale: u32,
+
+    /// Do not print the final screen
+    #[arg(long)]
+    no_ascii: bool,
+
+    /// Play back a movie recorded by the frontend, runs as many frames as the movie has
+    #[arg(long, conflicts_with_all = ["frames", "instructions", "cpu_hz", "ipf", "seed", "keys"])]
+    replay: Option<String>,
+}
+
+// key is held down during frames first_frame..first_frame + frames
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct KeyPress {
+    first_frame: u64,
+    key: usize,
+    frames: u64,
+}
+
+impl KeyPress {
+    fn is_down(&self, frame: u64) -> bool {
+        (self.first_frame..self.first_frame + self.frames).contains(&frame)
+    }
+}
+
+fn parse_key_press(value: &str) -> Result<KeyPress, String> {
+    let parts: Vec<&str> = value.split(':').collect();
+    let invalid = || format!("invalid key press `{}`, expected FRAME:KEY[:FRAMES]", value);
+
+    let (first_frame, key, frames) = match parts.as_slice() {
+        [first_frame, key] => (first_frame, key, "1"),
+        [first_frame, key, frames] => (first_frame, key, *frames),
+        _ => return Err(invalid()),
+    };
+    let key = usize::from_str_radix(key, 16).map_err(|_| invalid())?;
+    if key >= chip8::NUMBER_OF_KEYS {
+        return Err(format!("invalid key `{:X}`, keys are 0 to F", key));
+    }
+
+    Ok(KeyPress {
+        first_frame: first_frame.parse().map_err(|_| invalid())?,
+        key,
+        frames: frames.parse().map_err(|_| invalid())?,
+    })
+}
+
+// character for each pixel value, bit 0 is the first plane and bit 1 the second one
+const ASCII_PIXELS: [char; 4] = ['.', '#', '+', '@'];
+// same colors as the raylib frontend
+const PNG_COLORS: [[u8; 3]; 4] = [[0, 0, 0], [255, 255, 255], [130, 130, 130], [80, 80, 80]];
+
+fn screen_to_ascii(chip: &Chip8) -> String {
+    let mut ascii = String::new();
+    for row in chip.get_vram().chunks(chip.width()) {
+        ascii.extend(row.iter().map(|pixel| ASCII_PIXELS[*pixel as usize]));
+        ascii.push('\n');
+    }
+    ascii
+}
+
+fn registers_to_string(chip: &Chip8) -> String {
+    let registers: Vec<String> = chip
+        .get_registers()
+        .iter()
+        .enumerate()
+        .map(|(register, value)| format!("V{:X} {:02X}", register, value))
+        .collect();
+    let stack: Vec<String> = chip
+        .get_stack()
+        .iter()
+        .map(|address| format!("{:#05X}", address))
+        .collect();
+
+    format!(
+        "PC {:#05X}  I {:#05X}  DT {:02X}  ST {:02X}\n{}\nstack [{}]\n",
+        chip.get_pc(),
+        chip.get_i(),
+        chip.get_delay_timer(),
+        chip.get_sound_timer(),
+        registers.join("  "),
+        stack.join(", ")
+    )
+}
+
+fn write_png(chip: &Chip8, path: &str, scale: u32) -> Result<(), String> {
+    let scale = scale.max(1) as usize;
+    let (width, height) = (chip.width() * scale, chip.height() * scale);
+
+    let mut image = Vec::with_capacity(width * height * 3);
+    for row in chip.get_vram().chunks(chip.width()) {
+        let line: Vec<u8> = row
+            .iter()
+            .flat_map(|pixel| PNG_COLORS[*pixel as usize].repeat(scale))
+            .collect();
+        for _ in 0..scale {
+            image.extend_from_slice(&line);
+        }
+    }
+
+    let file = File::create(path).map_err(|error| error.to_string())?;
+    let mut encoder = png::Encoder::new(BufWriter::new(file), width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&image))
+        .map_err(|error| error.to_string())
+}
+
+fn set_keys(chip: &mut Chip8, keys: &[KeyPress], frame: u64) {
+    for key in 0..chip8::NUMBER_OF_KEYS {
+        let down = keys
+            .iter()
+            .any(|press| press.key == key && press.is_down(frame));
+        chip.set_key(key, down)
+            .expect("keys are below NUMBER_OF_KEYS");
+    }
+}
+
+fn main() -> ExitCode {
+    let args = Args::parse();
+    let rom = match std::fs::read(&args.rom) {
+        Ok(rom) => rom,
+        Err(error) => {
+            eprintln!("can't read {}: {}", args.rom, error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    if let Some(path) = &args.replay {
+        let replay = std::fs::read_to_string(path)
+            .map_err(|error| format!("can't read {}: {}", path, error))
+            .and_then(|text| text.parse::<Movie>().map_err(|error| error.to_string()));
+        return match replay {
+            Ok(movie) => run_replay(&args, &movie, &rom),
+            Err(error) => {
+                eprintln!("{}", error);
+                ExitCode::FAILURE
+            }
+        };
+    }
+
+    let mut chip = Chip8::new(args.quirks);
+    chip.set_cpu_hz(match args.ipf {
+        Some(ipf) => ipf * chip8::TIMER_HZ,
+        None => args.cpu_hz,
+    });
+    chip.set_seed(args.seed);
+
+    let mut result = chip.load(&rom);
+    match args.instructions {
+        Some(instructions) => {
+            if result.is_ok() {
+                result = run_instructions(&mut chip, &args.keys, instructions);
+            }
+        }
+        None => {
+            for frame in 0..args.frames as u64 {
+                if result.is_err() || chip.has_exited() {
+                    break;
+                }
+                set_keys(&mut chip, &args.keys, frame);
+                result = chip.run_frame().map(|_| ());
+            }
+        }
+    }
+
+    report(&args, &chip, result)
+}
+
+// timers still tick at 60 Hz of emulated time, key presses are timed in the same frames
+// like run_frame, a frame ends early when a draw waits for the vertical blank
+fn run_instructions(
+    chip: &mut Chip8,
+    keys: &[KeyPress],
+    instructions: u64,
+) -> Result<(), Chip8Error> {
+    let instructions_per_frame = (chip.get_cpu_hz() / chip8::TIMER_HZ).max(1) as u64;
+    let mut frame = 0;
+    let mut in_frame = 0;
+    set_keys(chip, keys, frame);
+    for _ in 0..instructions {
+        if chip.has_exited() {
+            break;
+        }
+        if in_frame == instructions_per_frame || chip.is_waiting_for_vblank() {
+            chip.tick_timers();
+            frame += 1;
+            in_frame = 0;
+            set_keys(chip, keys, frame);
+        }
+        chip.tick()?;
+        in_frame += 1;
+    }
+    Ok(())
+}
+
+fn run_replay(args: &Args, movie: &Movie, rom: &[u8]) -> ExitCode {
+    let mut chip = match movie.start(rom, args.quirks) {
+        Ok(chip) => chip,
+        Err(error) => {
+            eprintln!("{}", error);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let mut result = Ok(());
+    let mut frame = 0;
+    while result.is_ok() && !chip.has_exited() && movie.play(frame, &mut chip) {
+        result = chip.run_frame().map(|_| ());
+        frame += 1;
+    }
+    report(args, &chip, result)
+}
+
+// prints the final state, writes the PNG and turns the result into the exit code
+fn report(args: &Args, chip: &Chip8, result: Result<(), Chip8Error>) -> ExitCode {
+    if !args.no_ascii {
+        print!("{}", screen_to_ascii(chip));
+    }
+    print!("{}", registers_to_string(chip));
+    if let Some(path) = &args.png {
+        if let Err(error) = write_png(chip, path, args.png_scale) {
+            eprintln!("can't write {}: {}", path, error);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_key_press() {
+        assert_eq!(
+            Ok(KeyPress {
+                first_frame: 30,
+                key: 0xA,
+                frames: 1
+            }),
+            parse_key_press("30:A")
+        );
+        assert_eq!(
+            Ok(KeyPress {
+                first_frame: 0,
+                key: 5,
+                frames: 10
+            }),
+            parse_key_press("0:5:10")
+        );
+        assert!(parse_key_press("5").is_err());
+        assert!(parse_key_press("1:10").is_err());
+        assert!(parse_key_press("x:1").is_err());
+    }
+
+    #[test]
+    fn test_ipf_is_limited_to_a_u32_cpu_speed() {
+        let parse =
+            |ipf: u32| Args::try_parse_from(["headless", "-r", "rom", "--ipf", &ipf.to_string()]);
+        assert_eq!(Some(chip8::MAX_IPF), parse(chip8::MAX_IPF).unwrap().ipf);
+        assert!(parse(chip8::MAX_IPF + 1).is_err());
+    }
+
+    #[test]
+    fn test_cpu_hz_is_limited() {
+        let parse =
+            |hz: u32| Args::try_parse_from(["headless", "-r", "rom", "--cpu-hz", &hz.to_string()]);
+        assert_eq!(chip8::MAX_CPU_HZ, parse(chip8::MAX_CPU_HZ).unwrap().cpu_hz);
+        assert!(parse(chip8::MAX_CPU_HZ + 1).is_err());
+        assert!(parse(0).is_err());
+    }
+
+    #[test]
+    fn test_key_press_is_down() {
+        let press = parse_key_press("10:1:2").unwrap();
+        assert!(!press.is_down(9));
+        assert!(press.is_down(10));
+        assert!(press.is_down(11));
+        assert!(!press.is_down(12));
+    }
+
+    #[test]
+    fn test_run_instructions_ends_frame_on_display_wait() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.set_cpu_hz(600);
+        // LD V0, 10; LD DT, V0; loop: DRW V0, V0, 1; JP loop
+        chip.load(&[0x60, 0x0A, 0xF0, 0x15, 0xD0, 0x01, 0x12, 0x04])
+            .unwrap();
+
+        // the timer ticks after each draw instead of every 10 instructions
+        run_instructions(&mut chip, &[], 8).unwrap();
+        assert_eq!(7, chip.get_delay_timer());
+    }
+
+    #[test]
+    fn test_screen_to_ascii() {
+        let mut chip = Chip8::new(Quirks::default());
+        // draw the top row of the "0" font sprite at (0, 0)
+        chip.load(&[0xF0, 0x29, 0xD0, 0x01]).unwrap();
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+
+        let ascii = screen_to_ascii(&chip);
+        let lines: Vec<&str> = ascii.lines().collect();
+        assert_eq!(chip8::HEIGHT, lines.len());
+        assert_eq!(format!("####{}", ".".repeat(60)), lines[0]);
+        assert_eq!(".".repeat(64), lines[1]);
+    }
+
+    #[test]
+    fn test_registers_to_string() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&[0x6B, 0x2A, 0x22, 0x00]).unwrap();
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+
+        let registers = registers_to_string(&chip);
+        assert!(registers.starts_with("PC 0x200  I 0x000  DT 00  ST 00\n"));
+        assert!(registers.contains("  VB 2A  "));
+        assert!(registers.ends_with("stack [0x204]\n"));
+    }
+}
@@ -0,0 +1,240 @@
+use std::collections::BTreeSet;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+// kind of memory access a watchpoint triggers on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Access {
+    fn includes(self, access: Access) -> bool {
+        self == Access::ReadWrite || self == access
+    }
+}
+
+// stop when an instruction reads or writes memory in `addresses`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+    pub addresses: RangeInclusive<usize>,
+    pub access: Access,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    LessThan,
+    GreaterThan,
+}
+
+// stop when VX compared to `value` becomes true, e.g. VA == 0x10
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RegisterCondition {
+    pub register: usize,
+    pub comparison: Comparison,
+    pub value: u8,
+}
+
+impl RegisterCondition {
+    pub fn matches(&self, registers: &[u8]) -> bool {
+        let vx = registers[self.register];
+        match self.comparison {
+            Comparison::Equal => vx == self.value,
+            Comparison::NotEqual => vx != self.value,
+            Comparison::LessThan => vx < self.value,
+            Comparison::GreaterThan => vx > self.value,
+        }
+    }
+}
+
+// why Chip8::tick stopped, the instruction which caused it has already been executed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StopReason {
+    // PC reached a breakpoint, instruction at `pc` is executed next
+    Breakpoint {
+        pc: usize,
+    },
+    Condition(RegisterCondition),
+    // instruction at `pc` accessed `address`, access is either Read or Write
+    Watchpoint {
+        address: usize,
+        access: Access,
+        pc: usize,
+    },
+}
+
+impl fmt::Display for StopReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StopReason::Breakpoint { pc } => write!(f, "breakpoint at {:#05X}", pc),
+            StopReason::Condition(condition) => {
+                let operator = match condition.comparison {
+                    Comparison::Equal => "==",
+                    Comparison::NotEqual => "!=",
+                    Comparison::LessThan => "<",
+                    Comparison::GreaterThan => ">",
+                };
+                write!(
+                    f,
+                    "condition V{:X} {} {:#04X}",
+                    condition.register, operator, condition.value
+                )
+            }
+            StopReason::Watchpoint {
+                address,
+                access,
+                pc,
+            } => {
+                let verb = match access {
+                    Access::Write => "write to",
+                    _ => "read from",
+                };
+                write!(f, "watchpoint: {} {:#06X} at {:#05X}", verb, address, pc)
+            }
+        }
+    }
+}
+
+// everything execution can be stopped on, empty by default
+#[derive(Clone, Debug, Default)]
+pub struct Breakpoints {
+    addresses: BTreeSet<usize>,
+    conditions: Vec<RegisterCondition>,
+    watchpoints: Vec<Watchpoint>,
+}
+
+impl Breakpoints {
+    pub fn add_breakpoint(&mut self, pc: usize) {
+        self.addresses.insert(pc);
+    }
+
+    pub fn remove_breakpoint(&mut self, pc: usize) -> bool {
+        self.addresses.remove(&pc)
+    }
+
+    // adds the breakpoint if there is none at `pc`, removes it otherwise
+    // returns whether there is a breakpoint now
+    pub fn toggle_breakpoint(&mut self, pc: usize) -> bool {
+        if self.remove_breakpoint(pc) {
+            false
+        } else {
+            self.add_breakpoint(pc);
+            true
+        }
+    }
+
+    pub fn has_breakpoint(&self, pc: usize) -> bool {
+        self.addresses.contains(&pc)
+    }
+
+    pub fn add_condition(&mut self, condition: RegisterCondition) {
+        self.conditions.push(condition);
+    }
+
+    pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+        self.watchpoints.push(watchpoint);
+    }
+
+    pub fn clear(&mut self) {
+        *self = Breakpoints::default();
+    }
+
+    pub(crate) fn check_breakpoint(&self, pc: usize) -> Option<StopReason> {
+        self.has_breakpoint(pc)
+            .then_some(StopReason::Breakpoint { pc })
+    }
+
+    // conditions only trigger when they become true, not on every instruction while they hold
+    pub(crate) fn check_conditions(&self, before: &[u8], after: &[u8]) -> Option<StopReason> {
+        self.conditions
+            .iter()
+            .find(|condition| condition.matches(after) && !condition.matches(before))
+            .map(|condition| StopReason::Condition(*condition))
+    }
+
+    pub(crate) fn check_watchpoints(
+        &self,
+        address: usize,
+        access: Access,
+        pc: usize,
+    ) -> Option<StopReason> {
+        self.watchpoints
+            .iter()
+            .any(|watchpoint| {
+                watchpoint.access.includes(access) && watchpoint.addresses.contains(&address)
+            })
+            .then_some(StopReason::Watchpoint {
+                address,
+                access,
+                pc,
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_toggle_breakpoint() {
+        let mut breakpoints = Breakpoints::default();
+
+        assert!(breakpoints.toggle_breakpoint(0x204));
+        assert!(breakpoints.has_breakpoint(0x204));
+        assert!(!breakpoints.toggle_breakpoint(0x204));
+        assert!(!breakpoints.has_breakpoint(0x204));
+    }
+
+    #[test]
+    fn test_condition_triggers_on_transition() {
+        let mut breakpoints = Breakpoints::default();
+        let condition = RegisterCondition {
+            register: 0x3,
+            comparison: Comparison::GreaterThan,
+            value: 0x10,
+        };
+        breakpoints.add_condition(condition);
+
+        let mut before = [0; 16];
+        let mut after = [0; 16];
+        after[0x3] = 0x11;
+        assert_eq!(
+            Some(StopReason::Condition(condition)),
+            breakpoints.check_conditions(&before, &after)
+        );
+
+        before[0x3] = 0x11;
+        after[0x3] = 0x12;
+        assert_eq!(None, breakpoints.check_conditions(&before, &after));
+    }
+
+    #[test]
+    fn test_watchpoint_access() {
+        let mut breakpoints = Breakpoints::default();
+        breakpoints.add_watchpoint(Watchpoint {
+            addresses: 0x300..=0x30F,
+            access: Access::Write,
+        });
+
+        assert_eq!(
+            None,
+            breakpoints.check_watchpoints(0x300, Access::Read, 0x200)
+        );
+        assert_eq!(
+            None,
+            breakpoints.check_watchpoints(0x310, Access::Write, 0x200)
+        );
+        assert_eq!(
+            Some(StopReason::Watchpoint {
+                address: 0x30F,
+                access: Access::Write,
+                pc: 0x200
+            }),
+            breakpoints.check_watchpoints(0x30F, Access::Write, 0x200)
+        );
+    }
+}
@@ -1,27 +1,103 @@
-use rand::prelude::*;
+use crate::breakpoints::{Access, Breakpoints, StopReason};
+use crate::error::Chip8Error;
+use crate::instruction::Instruction;
+use crate::quirks::{MemoryIncrement, Quirks};
+use crate::random::{RandomSource, SeededRandom};
+use crate::save_state::{SaveStateError, StateReader, StateWriter};
 use std::collections::VecDeque;
 
 pub const WIDTH: usize = 64;
 pub const HEIGHT: usize = 32;
-const RAM_SIZE: usize = 4096;
-const VRAM_SIZE: usize = WIDTH * HEIGHT;
-const NUMBER_OF_REGISTERS: usize = 16;
-const NUMBER_OF_KEYS: usize = 16;
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+pub const TIMER_HZ: u32 = 60;
+pub const DEFAULT_CPU_HZ: u32 = 700;
+// largest CPU speed in instructions per frame whose instructions per second fit in a u32
+pub const MAX_IPF: u32 = u32::MAX / TIMER_HZ;
+pub const MAX_CPU_HZ: u32 = MAX_IPF * TIMER_HZ;
+const RAM_SIZE: usize = 0x10000; // XO-CHIP 64 KiB address space
+const VRAM_SIZE: usize = HIRES_WIDTH * HIRES_HEIGHT;
+pub const NUMBER_OF_REGISTERS: usize = 16;
+pub const NUMBER_OF_KEYS: usize = 16;
+const NUMBER_OF_RPL_FLAGS: usize = 16;
+const STACK_SIZE: usize = 16;
+pub const PROGRAM_START: usize = 0x200;
+// seed of the random numbers of a new machine, frontends pick their own with set_seed
+pub const DEFAULT_SEED: u64 = 0;
+const FONT_ADDRESS: usize = 0x50;
+const BIG_FONT_ADDRESS: usize = 0xA0;
+const AUDIO_PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64;
+// each VRAM cell holds one bit per plane
+const PLANE_MASK: u8 = 0b11;
+// awaited key in save states when Fx0A is not waiting
+const NO_KEY: u8 = 0xFF;
+
+// XO-CHIP audio pattern, 128 1-bit samples played in a loop
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioPattern {
+    pub buffer: [u8; AUDIO_PATTERN_SIZE],
+    pub pitch: u8,
+}
+
+impl AudioPattern {
+    // samples per second
+    pub fn playback_rate(&self) -> f32 {
+        4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
+    }
+}
+
+// keypad input of one frame, bit N is key N
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct KeypadInput {
+    // keys down at the end of the frame
+    pub down: u16,
+    // keys pressed during the frame, those not down any more were tapped between two frames
+    pub pressed: u16,
+}
 
 pub struct Chip8 {
     ram: [u8; RAM_SIZE],
-    vram: [bool; VRAM_SIZE],
+    vram: [u8; VRAM_SIZE],
     pc: usize,
     registers: [u8; NUMBER_OF_REGISTERS],
     i: u16,
     stack: VecDeque<usize>,
-    pub keypad: [bool; NUMBER_OF_KEYS],
+    keypad: [bool; NUMBER_OF_KEYS],
+    // presses and releases not applied to the keypad yet, see apply_key_events
+    key_events: VecDeque<(usize, bool)>,
+    // keys pressed since the last vertical blank, they can't be released before the next one
+    pressed_this_frame: u16,
+    // key Fx0A saw down, the instruction completes when it is released
+    awaited_key: Option<usize>,
     delay_timer: u8,
     sound_timer: u8,
+    cpu_hz: u32,
+    cycle_remainder: u32,
+    quirks: Quirks,
+    waiting_for_vblank: bool,
+    // set when VRAM or the resolution changes, see take_display_changed
+    display_changed: bool,
+    hires: bool,
+    rpl_flags: [u8; NUMBER_OF_RPL_FLAGS],
+    exited: bool,
+    planes: u8,
+    audio_buffer: Option<[u8; AUDIO_PATTERN_SIZE]>,
+    pitch: u8,
+    // address of the instruction being executed, for error reporting
+    instruction_address: usize,
+    breakpoints: Breakpoints,
+    // first watchpoint hit by the instruction being executed
+    watchpoint_hit: Option<StopReason>,
+    // false until the first tick, which stops on a breakpoint at the start of the program
+    started: bool,
+    // Cxnn random numbers, seed is None when a custom source is used
+    seed: Option<u64>,
+    random: Box<dyn RandomSource>,
 }
 
 impl Chip8 {
-    pub fn new() -> Self {
+    pub fn new(quirks: Quirks) -> Self {
         let fontset: [u8; 80] = [
             0xF0, 0x90, 0x90, 0x90, 0xF0, // 0
             0x20, 0x60, 0x20, 0x20, 0x70, // 1
@@ -41,131 +117,621 @@ impl Chip8 {
             0xF0, 0x80, 0xF0, 0x80, 0x80, // F
         ];
 
+        // SUPER-CHIP 8x10 digits, A-F come from XO-CHIP
+        let big_fontset: [u8; 160] = [
+            0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, // 0
+            0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF, // 1
+            0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // 2
+            0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 3
+            0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03, // 4
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 5
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 6
+            0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18, // 7
+            0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, // 8
+            0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, // 9
+            0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, // A
+            0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, // B
+            0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C, // C
+            0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC, // D
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, // E
+            0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0, // F
+        ];
+
         let mut ram_with_fonts = [0; RAM_SIZE];
 
-        ram_with_fonts[0x50..=0x9F].copy_from_slice(&fontset);
+        ram_with_fonts[FONT_ADDRESS..FONT_ADDRESS + fontset.len()].copy_from_slice(&fontset);
+        ram_with_fonts[BIG_FONT_ADDRESS..BIG_FONT_ADDRESS + big_fontset.len()]
+            .copy_from_slice(&big_fontset);
 
         Chip8 {
             ram: ram_with_fonts,
-            vram: [false; VRAM_SIZE],
+            vram: [0; VRAM_SIZE],
             pc: 0x200,
             registers: [0; NUMBER_OF_REGISTERS],
             i: 0,
             stack: VecDeque::new(),
             keypad: [false; NUMBER_OF_KEYS], // 16 keys, 0..=F
+            key_events: VecDeque::new(),
+            pressed_this_frame: 0,
+            awaited_key: None,
             delay_timer: 0,
             sound_timer: 0,
+            cpu_hz: DEFAULT_CPU_HZ,
+            cycle_remainder: 0,
+            quirks,
+            waiting_for_vblank: false,
+            display_changed: true,
+            hires: false,
+            rpl_flags: [0; NUMBER_OF_RPL_FLAGS],
+            exited: false,
+            planes: 0b01,
+            audio_buffer: None,
+            pitch: DEFAULT_PITCH,
+            breakpoints: Breakpoints::default(),
+            watchpoint_hit: None,
+            started: false,
+            instruction_address: 0x200,
+            seed: Some(DEFAULT_SEED),
+            random: Box::new(SeededRandom::new(DEFAULT_SEED)),
+        }
+    }
+
+    pub fn load(&mut self, data: &[u8]) -> Result<(), Chip8Error> {
+        let max = RAM_SIZE - PROGRAM_START;
+        if data.len() > max {
+            return Err(Chip8Error::RomTooLarge {
+                size: data.len(),
+                max,
+            });
+        }
+
+        self.ram[PROGRAM_START..PROGRAM_START + data.len()].copy_from_slice(data);
+        Ok(())
+    }
+
+    // key is 0x0..=0xF
+    // presses and releases are queued and reach the program in order, a key is held down for at
+    // least until the next vertical blank, so the program sees taps shorter than a frame
+    pub fn set_key(&mut self, key: usize, pressed: bool) -> Result<(), Chip8Error> {
+        if key >= NUMBER_OF_KEYS {
+            return Err(Chip8Error::InvalidKey { key });
+        }
+        self.queue_key(key, pressed);
+        Ok(())
+    }
+
+    fn queue_key(&mut self, key: usize, pressed: bool) {
+        if self.queued_key(key) != pressed {
+            self.key_events.push_back((key, pressed));
         }
     }
 
-    fn get_pressed_key(&self) -> (bool, usize) {
-        for (idx, val) in self.keypad.iter().enumerate() {
-            if *val {
-                return (true, idx);
+    // whether the key is down once the queued events are applied
+    fn queued_key(&self, key: usize) -> bool {
+        self.key_events
+            .iter()
+            .rev()
+            .find(|(queued_key, _)| *queued_key == key)
+            .map_or(self.keypad[key], |(_, pressed)| *pressed)
+    }
+
+    // feed the input of a frame, call before running it
+    pub fn input(&mut self, input: KeypadInput) {
+        for key in 0..NUMBER_OF_KEYS {
+            let down = input.down & (1 << key) != 0;
+            if input.pressed & (1 << key) != 0 {
+                // pressed while already down, so it was released in between
+                if self.queued_key(key) {
+                    self.key_events.push_back((key, false));
+                }
+                self.queue_key(key, true);
             }
+            self.queue_key(key, down);
         }
-        (false, 0)
     }
 
-    pub fn load(&mut self, data: &[u8]) {
-        for (i, &byte) in data.iter().enumerate() {
-            let addr = 0x200 + i;
-            if addr < RAM_SIZE {
-                self.ram[addr] = byte;
+    // applies queued key events until one would release a key pressed in this frame, or press
+    // a key released by this call, so at least one instruction sees it released
+    fn apply_key_events(&mut self) {
+        let mut released = 0u16;
+        while let Some((key, pressed)) = self.key_events.front().copied() {
+            if !pressed && self.pressed_this_frame & (1 << key) != 0 {
+                break;
+            }
+            if pressed && released & (1 << key) != 0 {
+                break;
+            }
+            self.key_events.pop_front();
+            self.keypad[key] = pressed;
+            if pressed {
+                self.pressed_this_frame |= 1 << key;
             } else {
-                panic!("not enough RAM")
+                released |= 1 << key;
             }
         }
     }
 
-    pub fn get_vram(&self) -> &[bool; VRAM_SIZE] {
-        &self.vram
+    // restarts the default random number generator from `seed`
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = Some(seed);
+        self.random = Box::new(SeededRandom::new(seed));
+    }
+
+    // replaces the random number generator, e.g. with a fixed sequence in tests
+    pub fn set_random_source(&mut self, random: Box<dyn RandomSource>) {
+        self.seed = None;
+        self.random = random;
     }
 
-    fn fetch(&self) -> u16 {
-        if self.pc < 0x200 {
-            panic!("trying to access reserved memory");
+    pub fn get_seed(&self) -> Option<u64> {
+        self.seed
+    }
+
+    pub fn get_quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    pub fn get_keypad(&self) -> &[bool; NUMBER_OF_KEYS] {
+        &self.keypad
+    }
+
+    pub fn set_cpu_hz(&mut self, hz: u32) {
+        self.cpu_hz = hz;
+        self.cycle_remainder = 0;
+    }
+
+    pub fn get_cpu_hz(&self) -> u32 {
+        self.cpu_hz
+    }
+
+    // the beeper should sound for as long as the sound timer is non-zero
+    pub fn is_sound_playing(&self) -> bool {
+        self.sound_timer > 0
+    }
+
+    pub fn get_pc(&self) -> usize {
+        self.pc
+    }
+
+    pub fn get_i(&self) -> u16 {
+        self.i
+    }
+
+    pub fn get_registers(&self) -> &[u8; NUMBER_OF_REGISTERS] {
+        &self.registers
+    }
+
+    // return addresses, innermost call last
+    pub fn get_stack(&self) -> &VecDeque<usize> {
+        &self.stack
+    }
+
+    pub fn get_delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn get_sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    pub fn get_ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    // e.g. to preselect an option in the menu of a test ROM, which reads it from 0x1FF
+    pub fn set_ram(&mut self, address: usize, value: u8) -> Result<(), Chip8Error> {
+        let byte = self
+            .ram
+            .get_mut(address)
+            .ok_or(Chip8Error::AddressOutOfBounds { address })?;
+        *byte = value;
+        Ok(())
+    }
+
+    // width of the display in current resolution mode
+    pub fn width(&self) -> usize {
+        if self.hires {
+            HIRES_WIDTH
+        } else {
+            WIDTH
+        }
+    }
+
+    // height of the display in current resolution mode
+    pub fn height(&self) -> usize {
+        if self.hires {
+            HIRES_HEIGHT
+        } else {
+            HEIGHT
+        }
+    }
+
+    // width() * height() pixels, row by row
+    // bit 0 of a pixel is set if it is lit on the first plane, bit 1 for the second plane
+    pub fn get_vram(&self) -> &[u8] {
+        &self.vram[..self.width() * self.height()]
+    }
+
+    // true if the display changed since the last call, so frontends only redraw when needed
+    pub fn take_display_changed(&mut self) -> bool {
+        std::mem::take(&mut self.display_changed)
+    }
+
+    // set once the program loads an audio pattern with F002, plain beeper should be used otherwise
+    pub fn get_audio_pattern(&self) -> Option<AudioPattern> {
+        self.audio_buffer.map(|buffer| AudioPattern {
+            buffer,
+            pitch: self.pitch,
+        })
+    }
+
+    // program executed 00FD
+    pub fn has_exited(&self) -> bool {
+        self.exited
+    }
+
+    // DXYN with the display wait quirk, no more instructions run until the next tick_timers
+    pub fn is_waiting_for_vblank(&self) -> bool {
+        self.waiting_for_vblank
+    }
+
+    pub fn breakpoints(&self) -> &Breakpoints {
+        &self.breakpoints
+    }
+
+    pub fn breakpoints_mut(&mut self) -> &mut Breakpoints {
+        &mut self.breakpoints
+    }
+
+    // snapshot of the whole machine, see save_state.rs for the container format
+    // quirks, CPU speed and breakpoints are settings of the frontend and are not saved
+    // the seeded random number generator is saved with its position, a source given to
+    // set_random_source is not
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.bytes(&self.ram);
+        state.bytes(&self.vram);
+        state.u32(self.pc as u32);
+        state.bytes(&self.registers);
+        state.u16(self.i);
+        state.u8(self.stack.len() as u8);
+        for address in &self.stack {
+            state.u32(*address as u32);
+        }
+        for pressed in self.keypad {
+            state.bool(pressed);
+        }
+        state.u8(self.delay_timer);
+        state.u8(self.sound_timer);
+        state.u32(self.cycle_remainder);
+        state.bool(self.waiting_for_vblank);
+        state.bool(self.hires);
+        state.bytes(&self.rpl_flags);
+        state.bool(self.exited);
+        state.u8(self.planes);
+        state.bool(self.audio_buffer.is_some());
+        state.bytes(&self.audio_buffer.unwrap_or_default());
+        state.u8(self.pitch);
+        state.u8(self.awaited_key.map_or(NO_KEY, |key| key as u8));
+        let position = self.random.position();
+        state.bool(self.seed.is_some() && position.is_some());
+        state.u64(self.seed.unwrap_or_default());
+        state.u128(position.unwrap_or_default());
+        state.finish()
+    }
+
+    // restore a snapshot made by save_state, the machine is left untouched if it is invalid
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), SaveStateError> {
+        let mut state = StateReader::new(data)?;
+        let ram = state.array::<RAM_SIZE>()?;
+        let vram = state.array::<VRAM_SIZE>()?;
+        let pc = state.u32()? as usize;
+        if pc > RAM_SIZE {
+            return Err(SaveStateError::InvalidValue { field: "pc" });
+        }
+        let registers = state.array::<NUMBER_OF_REGISTERS>()?;
+        let i = state.u16()?;
+        let stack_size = state.u8()? as usize;
+        if stack_size > STACK_SIZE {
+            return Err(SaveStateError::InvalidValue { field: "stack" });
+        }
+        let mut stack = VecDeque::new();
+        for _ in 0..stack_size {
+            stack.push_back(state.u32()? as usize);
+        }
+        let mut keypad = [false; NUMBER_OF_KEYS];
+        for pressed in keypad.iter_mut() {
+            *pressed = state.bool("keypad")?;
+        }
+        let delay_timer = state.u8()?;
+        let sound_timer = state.u8()?;
+        let cycle_remainder = state.u32()?;
+        let waiting_for_vblank = state.bool("display wait")?;
+        let hires = state.bool("resolution")?;
+        let rpl_flags = state.array::<NUMBER_OF_RPL_FLAGS>()?;
+        let exited = state.bool("exit flag")?;
+        let planes = state.u8()?;
+        if planes & !PLANE_MASK != 0 {
+            return Err(SaveStateError::InvalidValue { field: "planes" });
+        }
+        let has_audio_buffer = state.bool("audio pattern")?;
+        let audio_buffer = state.array::<AUDIO_PATTERN_SIZE>()?;
+        let pitch = state.u8()?;
+        let awaited_key = match state.u8()? {
+            NO_KEY => None,
+            key if (key as usize) < NUMBER_OF_KEYS => Some(key as usize),
+            _ => {
+                return Err(SaveStateError::InvalidValue {
+                    field: "awaited key",
+                })
+            }
+        };
+        let has_seed = state.bool("random seed")?;
+        let seed = state.u64()?;
+        let position = state.u128()?;
+        state.finish()?;
+
+        self.ram = ram;
+        self.vram = vram;
+        self.display_changed = true;
+        self.pc = pc;
+        self.registers = registers;
+        self.i = i;
+        self.stack = stack;
+        self.keypad = keypad;
+        self.delay_timer = delay_timer;
+        self.sound_timer = sound_timer;
+        self.cycle_remainder = cycle_remainder;
+        self.waiting_for_vblank = waiting_for_vblank;
+        self.hires = hires;
+        self.rpl_flags = rpl_flags;
+        self.exited = exited;
+        self.planes = planes;
+        self.audio_buffer = has_audio_buffer.then_some(audio_buffer);
+        self.pitch = pitch;
+        self.awaited_key = awaited_key;
+        if has_seed {
+            self.set_seed(seed);
+            self.random.set_position(position);
+        }
+        // input from before the state was saved does not belong to it
+        self.key_events.clear();
+        self.pressed_this_frame = 0;
+        Ok(())
+    }
+
+    fn fetch(&self) -> Result<u16, Chip8Error> {
+        if self.pc < PROGRAM_START || self.pc + 1 >= RAM_SIZE {
+            return Err(Chip8Error::PcOutOfBounds { pc: self.pc });
         }
 
         let first: u16 = (self.ram[self.pc] as u16) << 8;
         let second: u16 = self.ram[self.pc + 1] as u16;
-        first | second
+        Ok(first | second)
     }
 
-    fn decode(&mut self, opcode: u16) {
-        let nibbles = (
-            ((opcode & 0xF000) >> 12) as u8,
-            ((opcode & 0x0F00) >> 8) as u8,
-            ((opcode & 0x00F0) >> 4) as u8,
-            (opcode & 0x000F) as u8,
-        );
+    fn read_ram(&mut self, address: usize) -> Result<u8, Chip8Error> {
+        self.check_watchpoints(address, Access::Read);
+        self.ram
+            .get(address)
+            .copied()
+            .ok_or(Chip8Error::MemoryAccessOutOfBounds {
+                address,
+                pc: self.instruction_address,
+            })
+    }
+
+    fn write_ram(&mut self, address: usize, value: u8) -> Result<(), Chip8Error> {
+        self.check_watchpoints(address, Access::Write);
+        match self.ram.get_mut(address) {
+            Some(byte) => {
+                *byte = value;
+                Ok(())
+            }
+            None => Err(Chip8Error::MemoryAccessOutOfBounds {
+                address,
+                pc: self.instruction_address,
+            }),
+        }
+    }
 
-        let nnn = (opcode & 0x0FFF) as usize;
-        let nn = (opcode & 0x00FF) as u8;
+    fn check_watchpoints(&mut self, address: usize, access: Access) {
+        if self.watchpoint_hit.is_none() {
+            self.watchpoint_hit =
+                self.breakpoints
+                    .check_watchpoints(address, access, self.instruction_address);
+        }
+    }
+
+    fn set_hires(&mut self, hires: bool) {
+        self.hires = hires;
+        self.vram = [0; VRAM_SIZE];
+        self.display_changed = true;
+    }
 
-        match nibbles {
-            // clear screen
-            (0x0, 0x0, 0xE, 0x0) => {
-                self.vram = [false; VRAM_SIZE];
+    // scroll selected planes by (dx, dy) pixels, pixels scrolled in from outside of the screen are off
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let (width, height) = (self.width() as isize, self.height() as isize);
+        let previous = self.vram;
+        self.display_changed = true;
+
+        for y in 0..height {
+            for x in 0..width {
+                let (from_x, from_y) = (x - dx, y - dy);
+                let scrolled_in = if (0..width).contains(&from_x) && (0..height).contains(&from_y) {
+                    previous[(from_x + from_y * width) as usize] & self.planes
+                } else {
+                    0
+                };
+
+                let location = (x + y * width) as usize;
+                self.vram[location] = (previous[location] & !self.planes) | scrolled_in;
+            }
+        }
+    }
+
+    // VX..=VY, in reverse order if X > Y
+    fn register_range(x: u8, y: u8) -> Vec<usize> {
+        if x <= y {
+            (x as usize..=y as usize).collect()
+        } else {
+            (y as usize..=x as usize).rev().collect()
+        }
+    }
+
+    // skip over the next instruction, F000 NNNN is 4 bytes long
+    fn skip_next_instruction(&mut self) {
+        if self.fetch() == Ok(0xF000) {
+            self.pc += 4;
+        } else {
+            self.pc += 2;
+        }
+    }
+
+    // wraps around like Fx1E, I can be 0xFFFF since F000 NNNN
+    fn increment_i_after_memory_access(&mut self, x: u8) {
+        match self.quirks.memory_increment {
+            MemoryIncrement::XPlusOne => self.i = self.i.wrapping_add(x as u16 + 1),
+            MemoryIncrement::X => self.i = self.i.wrapping_add(x as u16),
+            MemoryIncrement::Unchanged => (),
+        }
+    }
+
+    fn decode(&mut self, opcode: u16) -> Result<(), Chip8Error> {
+        match Instruction::parse(opcode) {
+            Some(instruction) => self.execute(instruction),
+            None => Err(Chip8Error::UnknownOpcode {
+                opcode,
+                address: self.instruction_address,
+            }),
+        }
+    }
+
+    fn execute(&mut self, instruction: Instruction) -> Result<(), Chip8Error> {
+        match instruction {
+            // clear selected planes
+            Instruction::Clear => {
+                for pixel in self.vram.iter_mut() {
+                    *pixel &= !self.planes;
+                }
+                self.display_changed = true;
             }
             // return from a subroutine
-            (0x0, 0x0, 0xE, 0xE) => {
-                self.pc = self.stack.pop_back().unwrap();
+            Instruction::Return => {
+                self.pc = self.stack.pop_back().ok_or(Chip8Error::StackUnderflow {
+                    address: self.instruction_address,
+                })?;
+            }
+            // scroll display N pixels down
+            Instruction::ScrollDown(n) => {
+                self.scroll(0, n as isize);
+            }
+            // scroll display N pixels up
+            Instruction::ScrollUp(n) => {
+                self.scroll(0, -(n as isize));
+            }
+            // scroll display 4 pixels right
+            Instruction::ScrollRight => {
+                self.scroll(4, 0);
+            }
+            // scroll display 4 pixels left
+            Instruction::ScrollLeft => {
+                self.scroll(-4, 0);
+            }
+            // exit interpreter
+            Instruction::Exit => {
+                self.exited = true;
+            }
+            // switch to low resolution (64x32) mode
+            Instruction::LowRes => {
+                self.set_hires(false);
+            }
+            // switch to high resolution (128x64) mode
+            Instruction::HighRes => {
+                self.set_hires(true);
             }
             // call machine code routine at NNN
-            (0x0, _, _, _) => {
+            Instruction::System(_) => {
                 // ignored by modern interpreters
             }
             // jump to address NNN
-            (0x1, _, _, _) => {
-                self.pc = nnn;
+            Instruction::Jump(nnn) => {
+                self.pc = nnn as usize;
             }
             // call subroutine at NNN
-            (0x2, _, _, _) => {
+            Instruction::Call(nnn) => {
+                if self.stack.len() == STACK_SIZE {
+                    return Err(Chip8Error::StackOverflow {
+                        address: self.instruction_address,
+                    });
+                }
                 self.stack.push_back(self.pc);
-                self.pc = nnn;
+                self.pc = nnn as usize;
             }
             // skip next instruction if VX equals NN
-            (0x3, x, _, _) => {
+            Instruction::SkipIfEqualByte(x, nn) => {
                 if self.registers[x as usize] == nn {
-                    self.pc += 2;
+                    self.skip_next_instruction();
                 }
             }
             // skip next instruction if VX does not equal NN
-            (0x4, x, _, _) => {
+            Instruction::SkipIfNotEqualByte(x, nn) => {
                 if self.registers[x as usize] != nn {
-                    self.pc += 2;
+                    self.skip_next_instruction();
                 }
             }
             // skip next instruction if VX equals VY
-            (0x5, x, y, 0) => {
+            Instruction::SkipIfEqual(x, y) => {
                 if self.registers[x as usize] == self.registers[y as usize] {
-                    self.pc += 2;
+                    self.skip_next_instruction();
+                }
+            }
+            // store VX..=VY in memory starting at memory location I, I is not modified
+            // registers are stored in reverse order if X > Y
+            Instruction::StoreRange(x, y) => {
+                for (offset, register) in Self::register_range(x, y).into_iter().enumerate() {
+                    self.write_ram(self.i as usize + offset, self.registers[register])?;
                 }
             }
-            (0x6, x, _, _) => {
+            // fill VX..=VY with values from memory starting at location I, I is not modified
+            // registers are filled in reverse order if X > Y
+            Instruction::LoadRange(x, y) => {
+                for (offset, register) in Self::register_range(x, y).into_iter().enumerate() {
+                    self.registers[register] = self.read_ram(self.i as usize + offset)?;
+                }
+            }
+            Instruction::SetByte(x, nn) => {
                 self.registers[x as usize] = nn;
             }
             // VX += nn
-            (0x7, x, _, _) => {
+            Instruction::AddByte(x, nn) => {
                 let vx = self.registers[x as usize] as u16;
                 let val = nn as u16;
                 let result = vx + val;
                 self.registers[x as usize] = result as u8;
             }
-            (0x8, x, y, 0) => {
+            Instruction::Set(x, y) => {
                 self.registers[x as usize] = self.registers[y as usize];
             }
-            (0x8, x, y, 1) => {
+            Instruction::Or(x, y) => {
                 self.registers[x as usize] |= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
             }
-            (0x8, x, y, 2) => {
+            Instruction::And(x, y) => {
                 self.registers[x as usize] &= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
             }
-            (0x8, x, y, 3) => {
+            Instruction::Xor(x, y) => {
                 self.registers[x as usize] ^= self.registers[y as usize];
+                if self.quirks.vf_reset {
+                    self.registers[0xF] = 0;
+                }
             }
-            (0x8, x, y, 4) => {
+            Instruction::Add(x, y) => {
                 let vx = self.registers[x as usize] as u16;
                 let vy = self.registers[y as usize] as u16;
                 let result = vx + vy;
@@ -180,258 +746,530 @@ impl Chip8 {
             }
             // substract VY from VX
             // set VF to 0 if underflow, 1 otherwise
-            (0x8, x, y, 5) => {
+            Instruction::Sub(x, y) => {
                 let vx = self.registers[x as usize];
                 let vy = self.registers[y as usize];
                 self.registers[x as usize] = vx.wrapping_sub(vy);
                 self.registers[0xF] = if vx >= vy { 1 } else { 0 };
             }
-            // set VF to the least-significant bit of VX
-            // shift VX right by one bit
-            // VX = VX >> 1
-            (0x8, x, _y, 6) => {
-                let vx = self.registers[x as usize];
-                let flag = vx & 1;
-                self.registers[x as usize] = vx >> 1;
+            // set VF to the least-significant bit of VY (or VX with shift quirk)
+            // VX = VY >> 1 (or VX = VX >> 1 with shift quirk)
+            Instruction::ShiftRight(x, y) => {
+                let value = self.registers[if self.quirks.shift { x } else { y } as usize];
+                let flag = value & 1;
+                self.registers[x as usize] = value >> 1;
                 self.registers[0xF] = flag;
             }
             // substract VX from VY
             // set VF to 0 if underflow, 1 otherwise
-            (0x8, x, y, 7) => {
+            Instruction::SubReverse(x, y) => {
                 let vx = self.registers[x as usize];
                 let vy = self.registers[y as usize];
                 self.registers[x as usize] = vy.wrapping_sub(vx);
                 self.registers[0xF] = if vy >= vx { 1 } else { 0 };
             }
-            // set VF to the least-significant bit of VX
-            // shift VX left by one bit
-            // VX = VX << 1
-            (0x8, x, _y, 0xE) => {
-                let vx = self.registers[x as usize];
-                let flag = vx & 1;
-                self.registers[x as usize] = vx << 1;
+            // set VF to the most-significant bit of VY (or VX with shift quirk)
+            // VX = VY << 1 (or VX = VX << 1 with shift quirk)
+            Instruction::ShiftLeft(x, y) => {
+                let value = self.registers[if self.quirks.shift { x } else { y } as usize];
+                let flag = value >> 7;
+                self.registers[x as usize] = value << 1;
                 self.registers[0xF] = flag;
             }
             // skip next instruction if VX does not equal VY
-            (0x9, x, y, 0) => {
+            Instruction::SkipIfNotEqual(x, y) => {
                 if self.registers[x as usize] != self.registers[y as usize] {
-                    self.pc += 2;
+                    self.skip_next_instruction();
                 }
             }
-            (0xA, _, _, _) => {
-                self.i = nnn as u16;
+            Instruction::SetI(nnn) => {
+                self.i = nnn;
             }
             // jump to address NNN + V0
-            (0xB, _, _, _) => {
-                self.pc = nnn + (self.registers[0] as usize);
+            // with jump quirk jump to address XNN + VX
+            Instruction::JumpWithOffset(x, nnn) => {
+                let offset_register = if self.quirks.jump_with_vx { x } else { 0 };
+                self.pc = nnn as usize + (self.registers[offset_register as usize] as usize);
             }
             // generate random number
             // binary AND with NN
             // store in VX
-            (0xC, x, _, _) => {
-                let mut rng = rand::thread_rng();
-                let mut random_number: u8 = rng.gen();
-                random_number &= nn;
-                self.registers[x as usize] = random_number;
+            Instruction::Random(x, nn) => {
+                self.registers[x as usize] = self.random.next_byte() & nn;
             }
-            (0xD, x, y, n) => {
-                let vram_x = self.registers[x as usize] as usize % WIDTH;
-                let vram_y = self.registers[y as usize] as usize % HEIGHT;
+            // draw sprite, N rows of 8 pixels from ram[I], at (VX, VY)
+            // N = 0 draws 16x16 sprite, two bytes per row
+            // sprites are clipped at the edges of the screen (or wrapped without clipping quirk)
+            // set VF to 1 if any pixel was turned off, 0 otherwise
+            Instruction::Draw(x, y, n) => {
+                let (width, height) = (self.width(), self.height());
+                let vram_x = self.registers[x as usize] as usize % width;
+                let vram_y = self.registers[y as usize] as usize % height;
                 self.registers[0xF] = 0;
 
-                let mut clip_x = 0;
-                let mut clip_y = 0;
+                let (rows, columns) = if n == 0 { (16, 16) } else { (n as usize, 8) };
+                let bytes_per_row = columns / 8;
+                let mut sprite_address = self.i as usize;
 
-                if WIDTH - vram_x < 8 {
-                    clip_x = 8 - (WIDTH - vram_x);
-                }
+                // every selected plane gets its own sprite, stored one after another
+                for plane in [0b01, 0b10] {
+                    if self.planes & plane == 0 {
+                        continue;
+                    }
 
-                if HEIGHT - 1 - vram_y < (n as usize) {
-                    clip_y = n as usize - (HEIGHT - 1 - vram_y);
-                }
+                    for row in 0..rows {
+                        let mut pixel_y = vram_y + row;
+                        if pixel_y >= height {
+                            if self.quirks.clipping {
+                                break;
+                            }
+                            pixel_y %= height;
+                        }
+
+                        let row_address = sprite_address + row * bytes_per_row;
+                        let mut sprite_data = (self.read_ram(row_address)? as u16) << 8;
+                        if bytes_per_row == 2 {
+                            sprite_data |= self.read_ram(row_address + 1)? as u16;
+                        }
 
-                for row in 0..(n - clip_y as u8) {
-                    let sprite_data = self.ram[(self.i + row as u16) as usize];
-                    for column in 0..(8 - clip_x) {
-                        let location = vram_x + column + ((vram_y + row as usize) * WIDTH);
-                        let sprite_pixel_on = (sprite_data & (0x80 >> column)) != 0;
-                        if sprite_pixel_on {
-                            if self.vram[location] {
-                                self.vram[location] = false;
-                                self.registers[0xF] = 1;
-                            } else {
-                                self.vram[location] = true;
+                        for column in 0..columns {
+                            let mut pixel_x = vram_x + column;
+                            if pixel_x >= width {
+                                if self.quirks.clipping {
+                                    break;
+                                }
+                                pixel_x %= width;
+                            }
+
+                            let location = pixel_x + pixel_y * width;
+                            let sprite_pixel_on = (sprite_data & (0x8000 >> column)) != 0;
+                            if sprite_pixel_on {
+                                self.display_changed = true;
+                                if self.vram[location] & plane != 0 {
+                                    self.vram[location] &= !plane;
+                                    self.registers[0xF] = 1;
+                                } else {
+                                    self.vram[location] |= plane;
+                                }
                             }
                         }
                     }
+
+                    sprite_address += rows * bytes_per_row;
+                }
+
+                if self.quirks.display_wait {
+                    self.waiting_for_vblank = true;
                 }
             }
             // skip next instruction if the key stored in VX is pressed
-            (0xE, x, 9, 0xE) => {
-                let vx = self.registers[x as usize];
+            Instruction::SkipIfKey(x) => {
+                let vx = self.registers[x as usize] & 0xF;
                 if self.keypad[vx as usize] {
-                    self.pc += 2;
+                    self.skip_next_instruction();
                 }
             }
             // skip next instruction if the key stored in VX is not pressed
-            (0xE, x, 0xA, 1) => {
-                let vx = self.registers[x as usize];
+            Instruction::SkipIfNotKey(x) => {
+                let vx = self.registers[x as usize] & 0xF;
                 if !self.keypad[vx as usize] {
-                    self.pc += 2;
+                    self.skip_next_instruction();
                 }
             }
+            // set I to NNNN, the 16-bit address stored after this instruction
+            Instruction::SetILong => {
+                self.i = self.fetch()?;
+                self.pc += 2;
+            }
+            // select drawing planes, N is a bitmask
+            Instruction::Planes(n) => {
+                self.planes = n & PLANE_MASK;
+            }
+            // load 16 bytes audio pattern from ram[I]
+            Instruction::Audio => {
+                let mut buffer = [0; AUDIO_PATTERN_SIZE];
+                for (offset, byte) in buffer.iter_mut().enumerate() {
+                    *byte = self.read_ram(self.i as usize + offset)?;
+                }
+                self.audio_buffer = Some(buffer);
+            }
             // set VX to the value of the delay timer
-            (0xF, x, 0, 7) => {
+            Instruction::GetDelay(x) => {
                 self.registers[x as usize] = self.delay_timer;
             }
             // wait for a keypress, store result in VX
-            (0xF, x, 0, 0xA) => {
-                let (found, key) = self.get_pressed_key();
-
-                if !found {
-                    self.pc -= 2; // decrement, we want to loop this until key is pressed
-                } else {
+            // wait until a key is pressed and released (like COSMAC VIP), store it in VX
+            // a key already down when waiting starts counts as pressed
+            Instruction::WaitForKey(x) => match self.awaited_key {
+                Some(key) if !self.keypad[key] => {
                     self.registers[x as usize] = key as u8;
+                    self.awaited_key = None;
                 }
+                awaited_key => {
+                    if awaited_key.is_none() {
+                        self.awaited_key = self.keypad.iter().position(|pressed| *pressed);
+                    }
+                    self.pc -= 2; // decrement, we want to loop this until key is released
+                }
+            },
+            // set audio pattern pitch to VX
+            Instruction::Pitch(x) => {
+                self.pitch = self.registers[x as usize];
             }
             // set delay timer to the value of VX
-            (0xF, x, 1, 5) => {
+            Instruction::SetDelay(x) => {
                 self.delay_timer = self.registers[x as usize];
             }
             // set sound timer to the value of VX
-            (0xF, x, 1, 8) => {
+            Instruction::SetSound(x) => {
                 self.sound_timer = self.registers[x as usize];
             }
             // set I to VX + I
-            (0xF, x, 1, 0xE) => {
-                self.i += self.registers[x as usize] as u16;
+            Instruction::AddI(x) => {
+                self.i = self.i.wrapping_add(self.registers[x as usize] as u16);
             }
             // set I to the location of sprite for the character in VX
-            (0xF, x, 2, 9) => {
-                let sprite_location = 0x50 + (self.registers[x as usize] * 5); // sprites are stored in ram starting from 0x50, each sprite is 5 bytes
+            // each sprite is 5 bytes
+            Instruction::Font(x) => {
+                let sprite_location =
+                    FONT_ADDRESS + (self.registers[x as usize] as usize & 0xF) * 5;
+                self.i = sprite_location as u16;
+            }
+            // set I to the location of big (8x10) sprite for the character in VX
+            // each sprite is 10 bytes
+            Instruction::BigFont(x) => {
+                let sprite_location =
+                    BIG_FONT_ADDRESS + (self.registers[x as usize] as usize & 0xF) * 10;
                 self.i = sprite_location as u16;
             }
             // store binary-coded decimal of VX
             // hundreds digit in ram[I]
             // tens digit in ram[I+1]
             // ones digit in ram[I+2]
-            (0xF, x, 3, 3) => {
+            Instruction::Bcd(x) => {
                 let vx = self.registers[x as usize];
                 let ix = self.i as usize;
-                self.ram[ix] = vx / 100;
-                self.ram[ix + 1] = (vx % 100) / 10;
-                self.ram[ix + 2] = vx % 10;
+                self.write_ram(ix, vx / 100)?;
+                self.write_ram(ix + 1, (vx % 100) / 10)?;
+                self.write_ram(ix + 2, vx % 10)?;
             }
             // store V0..=VX in memory starting at memory location I
-            (0xF, x, 5, 5) => {
+            Instruction::Store(x) => {
                 for i in 0..=x {
-                    self.ram[self.i as usize + i as usize] = self.registers[i as usize];
+                    self.write_ram(self.i as usize + i as usize, self.registers[i as usize])?;
                 }
+                self.increment_i_after_memory_access(x);
             }
             // fill V0..=VX with values from memory starting at location I
-            (0xF, x, 6, 5) => {
+            Instruction::Load(x) => {
                 for i in 0..=x {
-                    self.registers[i as usize] = self.ram[self.i as usize + i as usize];
+                    self.registers[i as usize] = self.read_ram(self.i as usize + i as usize)?;
                 }
+                self.increment_i_after_memory_access(x);
+            }
+            // store V0..=VX in RPL user flags
+            Instruction::StoreFlags(x) => {
+                self.rpl_flags[..=x as usize].copy_from_slice(&self.registers[..=x as usize]);
+            }
+            // fill V0..=VX from RPL user flags
+            Instruction::LoadFlags(x) => {
+                self.registers[..=x as usize].copy_from_slice(&self.rpl_flags[..=x as usize]);
+            }
+        }
+
+        Ok(())
+    }
+
+    // delay and sound timers count down at TIMER_HZ, independently of the CPU
+    // this is also the vertical blank, which display wait quirk is waiting for
+    pub fn tick_timers(&mut self) {
+        self.delay_timer = self.delay_timer.saturating_sub(1);
+        self.sound_timer = self.sound_timer.saturating_sub(1);
+        self.waiting_for_vblank = false;
+        self.pressed_this_frame = 0;
+    }
+
+    // execute a single instruction
+    // returns a stop reason if the instruction triggered a watchpoint or a register condition,
+    // or if the next instruction has a breakpoint on it
+    // the first tick stops without executing anything if the first instruction has a breakpoint
+    pub fn tick(&mut self) -> Result<Option<StopReason>, Chip8Error> {
+        if self.exited {
+            return Ok(None);
+        }
+        if !std::mem::replace(&mut self.started, true) {
+            if let Some(stop) = self.breakpoints.check_breakpoint(self.pc) {
+                return Ok(Some(stop));
             }
-            _ => (),
         }
+
+        self.apply_key_events();
+        let opcode = self.fetch()?;
+        self.instruction_address = self.pc;
+        self.pc += 2;
+
+        let registers = self.registers;
+        self.watchpoint_hit = None;
+        self.decode(opcode)?;
+
+        Ok(self
+            .watchpoint_hit
+            .take()
+            .or_else(|| {
+                self.breakpoints
+                    .check_conditions(&registers, &self.registers)
+            })
+            .or_else(|| {
+                // instructions which loop on themselves (Fx0A, jump to self) stop only once
+                if self.pc == self.instruction_address {
+                    None
+                } else {
+                    self.breakpoints.check_breakpoint(self.pc)
+                }
+            }))
     }
 
-    pub fn tick(&mut self) {
-        let opcode = self.fetch();
-        // TODO: remove this guard
-        if self.pc < RAM_SIZE - 2 {
-            self.pc += 2;
+    // emulate one TIMER_HZ frame: run cpu_hz / TIMER_HZ instructions, then tick the timers
+    // the remainder of the division is carried over, so the clock does not drift
+    // when a breakpoint triggers the frame ends right away, without ticking the timers
+    pub fn run_frame(&mut self) -> Result<Option<StopReason>, Chip8Error> {
+        // in u64 because any u32 speed plus the remainder may not fit in a u32
+        let cycles = self.cycle_remainder as u64 + self.cpu_hz as u64;
+        let instructions = cycles / TIMER_HZ as u64;
+        self.cycle_remainder = (cycles % TIMER_HZ as u64) as u32;
+
+        for _ in 0..instructions {
+            if let Some(reason) = self.tick()? {
+                return Ok(Some(reason));
+            }
+            if self.waiting_for_vblank {
+                break;
+            }
         }
-        self.decode(opcode);
+        self.tick_timers();
+        Ok(None)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::assembler::assemble;
+    use crate::breakpoints::{Comparison, RegisterCondition, Watchpoint};
+
+    // random source which returns the given numbers in a loop
+    struct Sequence(Vec<u8>);
+
+    impl RandomSource for Sequence {
+        fn next_byte(&mut self) -> u8 {
+            self.0.rotate_left(1);
+            *self.0.last().unwrap()
+        }
+    }
 
     #[test]
     fn test_default_values() {
-        let chip = Chip8::new();
+        let chip = Chip8::new(Quirks::default());
         assert_eq!(0x200, chip.pc);
         assert_eq!(0, chip.i);
     }
 
+    #[test]
+    fn test_load_rom_larger_than_4k() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.load(&[0xAB; 0x2000]).unwrap();
+        assert_eq!(0xAB, chip.ram[0x200]);
+        assert_eq!(0xAB, chip.ram[0x21FF]);
+    }
+
+    #[test]
+    fn test_load_rom_too_large() {
+        let mut chip = Chip8::new(Quirks::default());
+        assert_eq!(
+            Err(Chip8Error::RomTooLarge {
+                size: RAM_SIZE,
+                max: RAM_SIZE - 0x200
+            }),
+            chip.load(&[0; RAM_SIZE])
+        );
+    }
+
     #[test]
     fn test_fetch() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.ram[chip.pc] = 0xA2;
         chip.ram[chip.pc + 1] = 0xF0;
 
-        assert_eq!(0xA2F0, chip.fetch());
+        assert_eq!(Ok(0xA2F0), chip.fetch());
     }
 
     #[test]
-    #[should_panic]
     fn test_fetch_reserved() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.pc = 0x1FF;
-        chip.fetch();
+        assert_eq!(Err(Chip8Error::PcOutOfBounds { pc: 0x1FF }), chip.fetch());
+    }
+
+    #[test]
+    fn test_fetch_past_end_of_memory() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.pc = RAM_SIZE - 1;
+        assert_eq!(
+            Err(Chip8Error::PcOutOfBounds { pc: RAM_SIZE - 1 }),
+            chip.fetch()
+        );
     }
 
     #[test]
     fn test_00e0_should_clear_screen() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
 
         for e in chip.vram.iter_mut() {
-            *e = true;
+            *e = 1;
         }
 
-        chip.decode(0x00E0);
+        chip.decode(0x00E0).unwrap();
 
-        let iter = chip.vram.iter().filter(|x| **x == true);
+        let iter = chip.vram.iter().filter(|x| **x != 0);
         assert_eq!(0, iter.count());
     }
 
+    #[test]
+    fn test_00ff_00fe_switch_resolution_and_clear_screen() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        assert_eq!(WIDTH * HEIGHT, chip.get_vram().len());
+        chip.vram[0] = 1;
+
+        chip.decode(0x00FF).unwrap();
+        assert_eq!((HIRES_WIDTH, HIRES_HEIGHT), (chip.width(), chip.height()));
+        assert_eq!(HIRES_WIDTH * HIRES_HEIGHT, chip.get_vram().len());
+        assert_eq!(0, chip.vram[0]);
+
+        chip.vram[0] = 1;
+        chip.decode(0x00FE).unwrap();
+        assert_eq!((WIDTH, HEIGHT), (chip.width(), chip.height()));
+        assert_eq!(WIDTH * HEIGHT, chip.get_vram().len());
+        assert_eq!(0, chip.vram[0]);
+    }
+
+    #[test]
+    fn test_00cn_scroll_down() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.vram[5] = 1; // (5, 0)
+        chip.vram[5 + WIDTH * (HEIGHT - 1)] = 1; // (5, 31)
+
+        chip.decode(0x00C2).unwrap();
+        assert_eq!(1, chip.vram.iter().filter(|x| **x != 0).count());
+        assert_eq!(1, chip.vram[5 + WIDTH * 2]);
+    }
+
+    #[test]
+    fn test_00fb_00fc_scroll_right_and_left() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.vram[WIDTH + 2] = 1; // (2, 1)
+
+        chip.decode(0x00FB).unwrap();
+        assert_eq!(1, chip.vram[WIDTH + 6]);
+        assert_eq!(1, chip.vram.iter().filter(|x| **x != 0).count());
+
+        chip.decode(0x00FC).unwrap();
+        chip.decode(0x00FC).unwrap();
+        assert_eq!(0, chip.vram.iter().filter(|x| **x != 0).count()); // scrolled off screen
+    }
+
+    #[test]
+    fn test_00dn_scroll_up() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.vram[5 + WIDTH * 3] = 1; // (5, 3)
+
+        chip.decode(0x00D2).unwrap();
+        assert_eq!(1, chip.vram[5 + WIDTH]);
+        assert_eq!(1, chip.vram.iter().filter(|x| **x != 0).count());
+    }
+
+    #[test]
+    fn test_scroll_only_selected_planes() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.vram[0] = 0b11;
+
+        chip.decode(0xF201).unwrap(); // select plane 2
+        chip.decode(0x00C1).unwrap();
+        assert_eq!(0b01, chip.vram[0]);
+        assert_eq!(0b10, chip.vram[WIDTH]);
+    }
+
+    #[test]
+    fn test_00e0_clears_only_selected_planes() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.vram[0] = 0b11;
+        chip.vram[1] = 0b10;
+
+        chip.decode(0x00E0).unwrap(); // plane 1 is selected by default
+        assert_eq!(0b10, chip.vram[0]);
+        assert_eq!(0b10, chip.vram[1]);
+
+        chip.decode(0xF301).unwrap(); // select both planes
+        chip.decode(0x00E0).unwrap();
+        assert_eq!(0, chip.vram.iter().filter(|x| **x != 0).count());
+    }
+
+    #[test]
+    fn test_00fd_exit_stops_execution() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.ram[0x200] = 0x00;
+        chip.ram[0x201] = 0xFD;
+        chip.ram[0x202] = 0x60; // V0 = 0x42
+        chip.ram[0x203] = 0x42;
+
+        chip.tick().unwrap();
+        assert!(chip.has_exited());
+        chip.tick().unwrap();
+        assert_eq!(0x202, chip.pc);
+        assert_eq!(0, chip.registers[0]);
+    }
+
     #[test]
     fn test_1nnn_should_jump() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         assert_eq!(0x200, chip.pc);
 
-        chip.decode(0x142C);
-        assert_eq!(0x42C as usize, chip.pc);
+        chip.decode(0x142C).unwrap();
+        assert_eq!(0x42C, chip.pc);
     }
 
     #[test]
     fn test_6xnn_should_store_number_in_register_x() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         let register_number = 8;
         assert_eq!(0, chip.registers[register_number]);
 
-        chip.decode(0x6842);
+        chip.decode(0x6842).unwrap();
         assert_eq!(0x42, chip.registers[register_number]);
     }
 
     #[test]
     fn test_7xnn_should_add_value_to_register_x() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         let register_number = 8;
-        chip.decode(0x6842);
+        chip.decode(0x6842).unwrap();
         assert_eq!(0x42, chip.registers[register_number]);
 
-        chip.decode(0x7808);
+        chip.decode(0x7808).unwrap();
         assert_eq!(0x42 + 0x08, chip.registers[register_number]);
     }
 
     #[test]
     fn test_annn_store_address_in_register() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         assert_eq!(0, chip.i);
 
-        chip.decode(0xA123);
+        chip.decode(0xA123).unwrap();
         assert_eq!(0x123, chip.i);
     }
 
     #[test]
     fn test_dxyn_updates_vram() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         let x = 0;
         let y = 1;
         chip.registers[x] = 8;
@@ -444,53 +1282,81 @@ mod tests {
         let start_position = chip.registers[x] as usize + (chip.registers[y] as usize * WIDTH);
 
         // first row
-        assert_eq!([false; 8], chip.vram[start_position..start_position + 8]);
+        assert_eq!([0; 8], chip.vram[start_position..start_position + 8]);
         // second row
         assert_eq!(
-            [false; 8],
+            [0; 8],
             chip.vram[start_position + WIDTH..start_position + WIDTH + 8]
         );
 
-        chip.decode(0xD012);
+        chip.decode(0xD012).unwrap();
 
         // first row
         assert_eq!(
-            [true, false, false, true, true, false, false, true],
+            [1, 0, 0, 1, 1, 0, 0, 1],
             chip.vram[start_position..start_position + 8]
         );
         // second row
         assert_eq!(
-            [false, true, true, false, false, true, true, false],
+            [0, 1, 1, 0, 0, 1, 1, 0],
             chip.vram[start_position + WIDTH..start_position + WIDTH + 8]
         );
 
         assert_eq!(0, chip.registers[0xF]);
     }
 
+    #[test]
+    fn test_display_changed() {
+        let mut chip = Chip8::new(Quirks::default());
+        assert!(chip.take_display_changed());
+        assert!(!chip.take_display_changed());
+
+        chip.decode(0x6001).unwrap();
+        assert!(!chip.take_display_changed());
+
+        chip.i = 0x200;
+        chip.ram[0x200] = 0b1000_0000;
+        chip.decode(0xD001).unwrap();
+        assert!(chip.take_display_changed());
+
+        // empty sprite
+        chip.ram[0x200] = 0;
+        chip.decode(0xD001).unwrap();
+        assert!(!chip.take_display_changed());
+
+        chip.decode(0x00E0).unwrap();
+        assert!(chip.take_display_changed());
+        chip.decode(0x00C1).unwrap();
+        assert!(chip.take_display_changed());
+        chip.decode(0x00FF).unwrap();
+        assert!(chip.take_display_changed());
+
+        let state = chip.save_state();
+        chip.load_state(&state).unwrap();
+        assert!(chip.take_display_changed());
+    }
+
     #[test]
     fn test_dxyn_updates_vram_and_vf_register() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0] = 0;
 
         chip.i = 0x200;
         chip.ram[0x200] = 0b1000_0000;
-        chip.vram[0] = true;
+        chip.vram[0] = 1;
 
-        assert_eq!(
-            [true, false, false, false, false, false, false, false],
-            chip.vram[0..8]
-        );
+        assert_eq!([1, 0, 0, 0, 0, 0, 0, 0], chip.vram[0..8]);
         assert_eq!(0, chip.registers[0xF]);
 
-        chip.decode(0xD001);
+        chip.decode(0xD001).unwrap();
 
-        assert_eq!([false; 8], chip.vram[0..8]);
+        assert_eq!([0; 8], chip.vram[0..8]);
         assert_eq!(1, chip.registers[0xF]);
     }
 
     #[test]
     fn test_dxyn_updates_vram_and_clip_on_x() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         let position_x: usize = 62;
         chip.registers[0] = position_x as u8; // should update vram[62..=63] only
         chip.registers[1] = 0;
@@ -499,20 +1365,20 @@ mod tests {
         chip.ram[0x200] = 0xFF;
 
         assert_eq!(
-            [false, false, false, false, false, false, false, false],
+            [0, 0, 0, 0, 0, 0, 0, 0],
             chip.vram[position_x..position_x + 8],
         );
 
-        chip.decode(0xD011);
+        chip.decode(0xD011).unwrap();
         assert_eq!(
-            [true, true, false, false, false, false, false, false], // 62, 63, EDGE (clip here)
+            [1, 1, 0, 0, 0, 0, 0, 0], // 62, 63, EDGE (clip here)
             chip.vram[position_x..position_x + 8],
         );
     }
 
     #[test]
     fn test_dxyn_updates_vram_and_clip_on_y() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         let position_y: usize = 30;
         chip.registers[0] = 0;
         chip.registers[1] = position_y as u8;
@@ -525,38 +1391,114 @@ mod tests {
 
         // row 30
         assert_eq!(
-            [false, false, false, false, false, false, false, false],
+            [0, 0, 0, 0, 0, 0, 0, 0],
             chip.vram[position_y * WIDTH..position_y * WIDTH + 8],
         );
 
         // row 31
         assert_eq!(
-            [false, false, false, false, false, false, false, false],
+            [0, 0, 0, 0, 0, 0, 0, 0],
             chip.vram[position_y * WIDTH..position_y * WIDTH + 8],
         );
 
-        chip.decode(0xD014);
+        chip.decode(0xD014).unwrap();
 
         // row 30
         assert_eq!(
-            [true, true, true, true, false, false, false, false],
+            [1, 1, 1, 1, 0, 0, 0, 0],
             chip.vram[position_y * WIDTH..position_y * WIDTH + 8],
         );
 
         // row 31
         assert_eq!(
-            [true, true, true, true, false, false, false, false],
+            [1, 1, 1, 1, 0, 0, 0, 0],
             chip.vram[position_y * WIDTH..position_y * WIDTH + 8],
         );
     }
 
+    #[test]
+    fn test_dxyn_updates_vram_and_wrap_around() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.registers[0] = 62;
+        chip.registers[1] = 31;
+
+        chip.i = 0x200;
+        chip.ram[0x200] = 0xFF;
+        chip.ram[0x201] = 0xFF;
+
+        chip.decode(0xD012).unwrap();
+        // row 31
+        assert_eq!([1, 1], chip.vram[31 * WIDTH + 62..32 * WIDTH]);
+        assert_eq!([1; 6], chip.vram[31 * WIDTH..31 * WIDTH + 6]);
+        // row 0
+        assert_eq!([1, 1], chip.vram[62..WIDTH]);
+        assert_eq!([1; 6], chip.vram[0..6]);
+        assert_eq!(0, chip.registers[0xF]);
+    }
+
+    #[test]
+    fn test_dxyn_display_wait_ends_frame() {
+        let mut chip = Chip8::new(Quirks::COSMAC_VIP);
+        chip.ram[0x200] = 0xD0; // draw
+        chip.ram[0x201] = 0x01;
+        chip.ram[0x202] = 0xD0; // draw
+        chip.ram[0x203] = 0x01;
+
+        chip.run_frame().unwrap();
+        assert_eq!(0x202, chip.pc);
+        chip.run_frame().unwrap();
+        assert_eq!(0x204, chip.pc);
+    }
+
+    #[test]
+    fn test_dxy0_draws_16x16_sprite() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.decode(0x00FF).unwrap();
+        chip.registers[0] = 120;
+        chip.registers[1] = 2;
+
+        chip.i = 0x300;
+        for byte in 0..32 {
+            chip.ram[0x300 + byte] = 0xFF;
+        }
+
+        chip.decode(0xD010).unwrap();
+        let lit = chip.get_vram().iter().filter(|x| **x != 0).count();
+        assert_eq!(8 * 16, lit); // clipped at x = 128
+        assert_eq!(1, chip.vram[120 + 2 * HIRES_WIDTH]);
+        assert_eq!(1, chip.vram[127 + 17 * HIRES_WIDTH]);
+        assert_eq!(0, chip.vram[120 + 18 * HIRES_WIDTH]);
+        assert_eq!(0, chip.registers[0xF]);
+
+        chip.decode(0xD010).unwrap();
+        assert_eq!(0, chip.get_vram().iter().filter(|x| **x != 0).count());
+        assert_eq!(1, chip.registers[0xF]);
+    }
+
+    #[test]
+    fn test_dxyn_draws_on_selected_planes() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.i = 0x300;
+        chip.ram[0x300] = 0b1100_0000; // plane 1
+        chip.ram[0x301] = 0b1010_0000; // plane 2
+
+        chip.decode(0xF201).unwrap(); // plane 2 only, uses first sprite
+        chip.decode(0xD001).unwrap();
+        assert_eq!([0b10, 0b10, 0, 0], chip.vram[0..4]);
+
+        chip.decode(0xF301).unwrap(); // both planes, one sprite after another
+        chip.decode(0xD001).unwrap();
+        assert_eq!([0b01, 0b11, 0b10, 0], chip.vram[0..4]);
+        assert_eq!(1, chip.registers[0xF]);
+    }
+
     #[test]
     fn test_2nnn_should_update_pc_and_stack() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         assert_eq!(0x200, chip.pc);
         assert!(chip.stack.is_empty());
 
-        chip.decode(0x2123);
+        chip.decode(0x2123).unwrap();
 
         assert_eq!(Some(&0x200), chip.stack.back());
         assert_eq!(0x123, chip.pc);
@@ -564,373 +1506,634 @@ mod tests {
 
     #[test]
     fn test_00ee_should_pop_stack_and_update_pc() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         assert_eq!(0x200, chip.pc);
         assert!(chip.stack.is_empty());
         chip.stack.push_back(0x123);
 
-        chip.decode(0x00EE);
+        chip.decode(0x00EE).unwrap();
 
         assert!(chip.stack.is_empty());
         assert_eq!(0x123, chip.pc);
     }
 
+    #[test]
+    fn test_00ee_with_empty_stack_is_an_error() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.ram[0x200] = 0x00;
+        chip.ram[0x201] = 0xEE;
+
+        assert_eq!(
+            Err(Chip8Error::StackUnderflow { address: 0x200 }),
+            chip.tick()
+        );
+    }
+
+    #[test]
+    fn test_2nnn_with_full_stack_is_an_error() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.ram[0x200] = 0x22; // call 0x200, forever
+        chip.ram[0x201] = 0x00;
+
+        for _ in 0..16 {
+            chip.tick().unwrap();
+        }
+        assert_eq!(
+            Err(Chip8Error::StackOverflow { address: 0x200 }),
+            chip.tick()
+        );
+    }
+
+    #[test]
+    fn test_unknown_opcode_is_an_error() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.ram[0x200] = 0x60; // V0 = 0x01
+        chip.ram[0x201] = 0x01;
+        chip.ram[0x202] = 0x80; // 8008 is not a valid opcode
+        chip.ram[0x203] = 0x08;
+
+        chip.tick().unwrap();
+        assert_eq!(
+            Err(Chip8Error::UnknownOpcode {
+                opcode: 0x8008,
+                address: 0x202
+            }),
+            chip.tick()
+        );
+    }
+
     #[test]
     fn test_3xnn_should_skip_instruction() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0xA] = 0x42;
         assert_eq!(0x200, chip.pc);
 
-        chip.decode(0x3A42);
+        chip.decode(0x3A42).unwrap();
 
         assert_eq!(0x202, chip.pc);
     }
 
     #[test]
     fn test_3xnn_should_not_skip_instruction() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0xA] = 0x41;
         assert_eq!(0x200, chip.pc);
 
-        chip.decode(0x3A42);
+        chip.decode(0x3A42).unwrap();
 
         assert_eq!(0x200, chip.pc);
     }
 
     #[test]
     fn test_4xnn_should_skip_instruction() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0xA] = 0x41;
         assert_eq!(0x200, chip.pc);
 
-        chip.decode(0x4A42);
+        chip.decode(0x4A42).unwrap();
 
         assert_eq!(0x202, chip.pc);
     }
 
     #[test]
     fn test_4xnn_should_not_skip_instruction() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0xA] = 0x42;
         assert_eq!(0x200, chip.pc);
 
-        chip.decode(0x4A42);
+        chip.decode(0x4A42).unwrap();
 
         assert_eq!(0x200, chip.pc);
     }
 
     #[test]
     fn test_5xy0_should_skip_instruction() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0xA] = 0x41;
         chip.registers[0xB] = 0x41;
         assert_eq!(0x200, chip.pc);
 
-        chip.decode(0x5AB0);
+        chip.decode(0x5AB0).unwrap();
 
         assert_eq!(0x202, chip.pc);
     }
 
     #[test]
     fn test_5xy0_should_not_skip_instruction() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0xA] = 0x41;
         chip.registers[0xB] = 0x42;
         assert_eq!(0x200, chip.pc);
 
-        chip.decode(0x5AB0);
+        chip.decode(0x5AB0).unwrap();
 
         assert_eq!(0x200, chip.pc);
     }
 
     #[test]
     fn test_9xy0_should_skip_instruction() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0xA] = 0x41;
         chip.registers[0xB] = 0x42;
         assert_eq!(0x200, chip.pc);
 
-        chip.decode(0x9AB0);
+        chip.decode(0x9AB0).unwrap();
 
         assert_eq!(0x202, chip.pc);
     }
 
     #[test]
     fn test_9xy0_should_not_skip_instruction() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0xA] = 0x41;
         chip.registers[0xB] = 0x41;
         assert_eq!(0x200, chip.pc);
 
-        chip.decode(0x9AB0);
+        chip.decode(0x9AB0).unwrap();
 
         assert_eq!(0x200, chip.pc);
     }
     #[test]
+    fn test_3xnn_should_skip_long_instruction() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.ram[0x200] = 0xF0; // F000 NNNN
+        chip.ram[0x201] = 0x00;
+
+        chip.decode(0x3000).unwrap();
+        assert_eq!(0x204, chip.pc);
+    }
+    #[test]
+    fn test_5xy2_store_register_range_in_ram() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.registers[2] = 1;
+        chip.registers[3] = 2;
+        chip.registers[4] = 3;
+        chip.i = 0x300;
+
+        chip.decode(0x5242).unwrap();
+        assert_eq!([1, 2, 3], chip.ram[0x300..0x303]);
+        assert_eq!(0x300, chip.i);
+
+        chip.decode(0x5422).unwrap(); // reverse order
+        assert_eq!([3, 2, 1], chip.ram[0x300..0x303]);
+    }
+    #[test]
+    fn test_5xy3_fill_register_range_from_ram() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.ram[0x300] = 1;
+        chip.ram[0x301] = 2;
+        chip.ram[0x302] = 3;
+        chip.i = 0x300;
+
+        chip.decode(0x5243).unwrap();
+        assert_eq!([0, 0, 1, 2, 3, 0], chip.registers[0..6]);
+        assert_eq!(0x300, chip.i);
+
+        chip.decode(0x5423).unwrap(); // reverse order
+        assert_eq!([0, 0, 3, 2, 1, 0], chip.registers[0..6]);
+    }
+    #[test]
     fn test_8xy0_should_copy_vy_to_vx() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 8;
         chip.registers[0xA] = 16;
         assert_eq!(8, chip.registers[0x5]);
         assert_eq!(16, chip.registers[0xA]);
 
-        chip.decode(0x85A0);
+        chip.decode(0x85A0).unwrap();
         assert_eq!(16, chip.registers[0x5]);
         assert_eq!(16, chip.registers[0xA]);
     }
     #[test]
     fn test_8xy1_vx_or_vy() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 0b0001_1100;
         chip.registers[0xA] = 0b1011_0001;
 
-        chip.decode(0x85A1);
+        chip.decode(0x85A1).unwrap();
         assert_eq!(0b1011_1101, chip.registers[0x5]);
         assert_eq!(0b1011_0001, chip.registers[0xA]);
     }
     #[test]
     fn test_8xy2_vx_and_vy() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 0b0001_1100;
         chip.registers[0xA] = 0b1011_0001;
 
-        chip.decode(0x85A2);
+        chip.decode(0x85A2).unwrap();
         assert_eq!(0b0001_0000, chip.registers[0x5]);
         assert_eq!(0b1011_0001, chip.registers[0xA]);
     }
     #[test]
     fn test_8xy3_vx_xor_vy() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 0b0001_1100;
         chip.registers[0xA] = 0b1011_0001;
 
-        chip.decode(0x85A3);
+        chip.decode(0x85A3).unwrap();
         assert_eq!(0b1010_1101, chip.registers[0x5]);
         assert_eq!(0b1011_0001, chip.registers[0xA]);
     }
     #[test]
     fn test_8xy4_add_vy_to_vx_no_carry() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 12;
         chip.registers[0xA] = 30;
         chip.registers[0xF] = 0;
 
-        chip.decode(0x85A4);
+        chip.decode(0x85A4).unwrap();
         assert_eq!(42, chip.registers[0x5]);
         assert_eq!(30, chip.registers[0xA]);
         assert_eq!(0, chip.registers[0xF]);
     }
     #[test]
     fn test_8xy4_add_vy_to_vx_with_carry() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 8;
         chip.registers[0xA] = 0xFF;
         chip.registers[0xF] = 0;
 
-        chip.decode(0x85A4);
+        chip.decode(0x85A4).unwrap();
         assert_eq!(7, chip.registers[0x5]);
         assert_eq!(0xFF, chip.registers[0xA]);
         assert_eq!(1, chip.registers[0xF]);
     }
     #[test]
     fn test_8xy5_subtract_vy_from_vx_vx_is_bigger() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 8;
         chip.registers[0xA] = 2;
         chip.registers[0xF] = 0;
 
-        chip.decode(0x85A5);
+        chip.decode(0x85A5).unwrap();
         assert_eq!(6, chip.registers[0x5]);
         assert_eq!(2, chip.registers[0xA]);
         assert_eq!(1, chip.registers[0xF]);
     }
     #[test]
     fn test_8xy5_subtract_vy_from_vx_s_smaller() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 2;
         chip.registers[0xA] = 8;
         chip.registers[0xF] = 0;
 
-        chip.decode(0x85A5);
+        chip.decode(0x85A5).unwrap();
         assert_eq!(250, chip.registers[0x5]);
         assert_eq!(8, chip.registers[0xA]);
         assert_eq!(0, chip.registers[0xF]);
     }
     #[test]
     fn test_8xy5_subtract_vy_from_vx_vx_and_vy_are_equal() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 8;
         chip.registers[0xA] = 8;
         chip.registers[0xF] = 0;
 
-        chip.decode(0x85A5);
+        chip.decode(0x85A5).unwrap();
         assert_eq!(0, chip.registers[0x5]);
         assert_eq!(8, chip.registers[0xA]);
         assert_eq!(1, chip.registers[0xF]);
     }
     #[test]
     fn test_8xy6_shift_vx_right_lsb_1() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
         chip.registers[0xF] = 0;
         chip.registers[0x5] = 0b0110_0001;
 
-        chip.decode(0x8506);
+        chip.decode(0x8506).unwrap();
         assert_eq!(1, chip.registers[0xF]);
         assert_eq!(0b0011_0000, chip.registers[0x5]);
     }
     #[test]
     fn test_8xy6_shift_vx_right_lsb_0() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
         chip.registers[0xF] = 0;
         chip.registers[0x5] = 0b0110_0000;
 
-        chip.decode(0x8506);
+        chip.decode(0x8506).unwrap();
         assert_eq!(0, chip.registers[0xF]);
         assert_eq!(0b0011_0000, chip.registers[0x5]);
     }
     #[test]
     fn test_8xy6_shift_vx_right_use_vf_lsb_1() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
         chip.registers[0xF] = 0b0110_0001;
 
-        chip.decode(0x8F06);
+        chip.decode(0x8F06).unwrap();
         assert_eq!(1, chip.registers[0xF]);
     }
     #[test]
     fn test_8xy6_shift_vx_right_use_vf_lsb_0() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
         chip.registers[0xF] = 0b0110_0000;
 
-        chip.decode(0x8F06);
+        chip.decode(0x8F06).unwrap();
         assert_eq!(0, chip.registers[0xF]);
     }
     #[test]
     fn test_8xy7_subtract_vx_from_vy_vy_is_bigger() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 2;
         chip.registers[0xA] = 8;
         chip.registers[0xF] = 0;
 
-        chip.decode(0x85A7);
+        chip.decode(0x85A7).unwrap();
         assert_eq!(6, chip.registers[0x5]);
         assert_eq!(8, chip.registers[0xA]);
         assert_eq!(1, chip.registers[0xF]);
     }
     #[test]
     fn test_8xy7_subtract_vx_from_vy_vy_is_smaller() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 8;
         chip.registers[0xA] = 2;
         chip.registers[0xF] = 0;
 
-        chip.decode(0x85A7);
+        chip.decode(0x85A7).unwrap();
         assert_eq!(250, chip.registers[0x5]);
         assert_eq!(2, chip.registers[0xA]);
         assert_eq!(0, chip.registers[0xF]);
     }
     #[test]
     fn test_8xy7_subtract_vx_from_vy_vx_and_vy_are_equal() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x5] = 8;
         chip.registers[0xA] = 8;
         chip.registers[0xF] = 0;
 
-        chip.decode(0x85A7);
+        chip.decode(0x85A7).unwrap();
         assert_eq!(0, chip.registers[0x5]);
         assert_eq!(8, chip.registers[0xA]);
         assert_eq!(1, chip.registers[0xF]);
     }
     #[test]
-    fn test_8xye_shift_vx_left_lsb_1() {
-        let mut chip = Chip8::new();
+    fn test_8xye_shift_vx_left_msb_1() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
         chip.registers[0xF] = 0;
-        chip.registers[0x6] = 0b0110_0001;
+        chip.registers[0x6] = 0b1010_0001;
 
-        chip.decode(0x860E);
+        chip.decode(0x860E).unwrap();
         assert_eq!(1, chip.registers[0xF]);
-        assert_eq!(0b1100_0010, chip.registers[0x6]);
+        assert_eq!(0b0100_0010, chip.registers[0x6]);
     }
     #[test]
-    fn test_8xye_shift_vx_left_lsb_0() {
-        let mut chip = Chip8::new();
+    fn test_8xye_shift_vx_left_msb_0() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
         chip.registers[0xF] = 0;
-        chip.registers[0x6] = 0b0110_0000;
+        chip.registers[0x6] = 0b0110_0001;
 
-        chip.decode(0x860E);
+        chip.decode(0x860E).unwrap();
         assert_eq!(0, chip.registers[0xF]);
-        assert_eq!(0b1100_0000, chip.registers[0x6]);
+        assert_eq!(0b1100_0010, chip.registers[0x6]);
     }
     #[test]
-    fn test_8xye_shift_vx_left_use_vf_lsb_1() {
-        let mut chip = Chip8::new();
-        chip.registers[0xF] = 0b0110_0001;
+    fn test_8xye_shift_vx_left_use_vf_msb_1() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.registers[0xF] = 0b1010_0000;
 
-        chip.decode(0x8F0E);
+        chip.decode(0x8F0E).unwrap();
         assert_eq!(1, chip.registers[0xF]);
     }
     #[test]
-    fn test_8xye_shift_vx_left_use_vf_lsb_0() {
-        let mut chip = Chip8::new();
-        chip.registers[0xF] = 0b0110_0000;
+    fn test_8xye_shift_vx_left_use_vf_msb_0() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.registers[0xF] = 0b0110_0001;
 
-        chip.decode(0x8F0E);
+        chip.decode(0x8F0E).unwrap();
         assert_eq!(0, chip.registers[0xF]);
     }
     #[test]
     fn test_bnnn_jump_to_address_plus_v0() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0x0] = 5;
 
-        chip.decode(0xB123); // 0x123 = 291
+        chip.decode(0xB123).unwrap(); // 0x123 = 291
         assert_eq!(296, chip.pc);
     }
-    #[ignore]
+    #[test]
+    fn test_8xy6_shift_vy_right_into_vx() {
+        let mut chip = Chip8::new(Quirks::COSMAC_VIP);
+        chip.registers[0x5] = 0;
+        chip.registers[0xA] = 0b0110_0001;
+
+        chip.decode(0x85A6).unwrap();
+        assert_eq!(1, chip.registers[0xF]);
+        assert_eq!(0b0011_0000, chip.registers[0x5]);
+        assert_eq!(0b0110_0001, chip.registers[0xA]);
+    }
+    #[test]
+    fn test_8xye_shift_vy_left_into_vx() {
+        let mut chip = Chip8::new(Quirks::COSMAC_VIP);
+        chip.registers[0x5] = 0;
+        chip.registers[0xA] = 0b0110_0000;
+
+        chip.decode(0x85AE).unwrap();
+        assert_eq!(0, chip.registers[0xF]);
+        assert_eq!(0b1100_0000, chip.registers[0x5]);
+        assert_eq!(0b0110_0000, chip.registers[0xA]);
+    }
+    #[test]
+    fn test_8xy1_8xy2_8xy3_reset_vf() {
+        for opcode in [0x85A1, 0x85A2, 0x85A3] {
+            let mut chip = Chip8::new(Quirks::COSMAC_VIP);
+            chip.registers[0xF] = 1;
+            chip.decode(opcode).unwrap();
+            assert_eq!(0, chip.registers[0xF]);
+
+            let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+            chip.registers[0xF] = 1;
+            chip.decode(opcode).unwrap();
+            assert_eq!(1, chip.registers[0xF]);
+        }
+    }
+    #[test]
+    fn test_bnnn_jump_to_address_plus_vx() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.registers[0x0] = 5;
+        chip.registers[0x1] = 7;
+
+        chip.decode(0xB123).unwrap(); // 0x123 = 291
+        assert_eq!(298, chip.pc);
+    }
     #[test]
     fn test_cxnn_binary_and_random_with_nn_store_in_vx() {
-        // cannot test because of random number
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
+        chip.set_random_source(Box::new(Sequence(vec![0xAB, 0x5C])));
         chip.registers[0x0] = 5;
 
-        chip.decode(0xC0FF);
-        //assert_eq!(???, chip.registers[0]);
+        chip.decode(0xC0FF).unwrap();
+        assert_eq!(0xAB, chip.registers[0]);
+        chip.decode(0xC10F).unwrap();
+        assert_eq!(0x0C, chip.registers[1]);
+        assert_eq!(None, chip.get_seed());
+    }
+    #[test]
+    fn test_cxnn_is_deterministic_for_a_seed() {
+        let numbers = |seed: Option<u64>| {
+            let mut chip = Chip8::new(Quirks::default());
+            if let Some(seed) = seed {
+                chip.set_seed(seed);
+            }
+            (0..8)
+                .map(|_| {
+                    chip.decode(0xC0FF).unwrap();
+                    chip.registers[0]
+                })
+                .collect::<Vec<u8>>()
+        };
+        assert_eq!(numbers(None), numbers(Some(DEFAULT_SEED)));
+        assert_eq!(numbers(Some(7)), numbers(Some(7)));
+        assert_ne!(numbers(Some(7)), numbers(Some(8)));
+    }
+    #[test]
+    fn test_f000_nnnn_load_long_address_to_i() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.ram[0x200] = 0xF0;
+        chip.ram[0x201] = 0x00;
+        chip.ram[0x202] = 0xAB;
+        chip.ram[0x203] = 0xCD;
+
+        chip.tick().unwrap();
+        assert_eq!(0xABCD, chip.i);
+        assert_eq!(0x204, chip.pc);
+    }
+    #[test]
+    fn test_f002_fx3a_audio_pattern_and_pitch() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        assert_eq!(None, chip.get_audio_pattern());
+
+        chip.i = 0x300;
+        chip.ram[0x300..0x310].copy_from_slice(&[0xF0; 16]);
+        chip.decode(0xF002).unwrap();
+        let pattern = chip.get_audio_pattern().unwrap();
+        assert_eq!([0xF0; 16], pattern.buffer);
+        assert_eq!(4000.0, pattern.playback_rate());
+
+        chip.registers[1] = 64 + 48; // one octave up
+        chip.decode(0xF13A).unwrap();
+        assert_eq!(8000.0, chip.get_audio_pattern().unwrap().playback_rate());
     }
     #[test]
     fn test_fx07_set_vx_to_delay_timer() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.delay_timer = 8;
 
-        chip.decode(0xF507); // VX = 5
+        chip.decode(0xF507).unwrap(); // VX = 5
         assert_eq!(8, chip.registers[5]);
     }
     #[test]
     fn test_fx15_set_delay_timer_to_vx() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[6] = 8;
 
         assert_eq!(0, chip.delay_timer);
-        chip.decode(0xF615); // VX = 6
+        chip.decode(0xF615).unwrap(); // VX = 6
         assert_eq!(8, chip.delay_timer);
     }
     #[test]
     fn test_fx18_set_sound_timer_to_vx() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[6] = 8;
 
         assert_eq!(0, chip.sound_timer);
-        chip.decode(0xF618); // VX = 6
+        chip.decode(0xF618).unwrap(); // VX = 6
         assert_eq!(8, chip.sound_timer);
     }
     #[test]
+    fn test_tick_timers_decrements_delay_and_sound_timers() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.delay_timer = 2;
+        chip.sound_timer = 1;
+
+        chip.tick_timers();
+        assert_eq!(1, chip.delay_timer);
+        assert_eq!(0, chip.sound_timer);
+
+        chip.tick_timers();
+        assert_eq!(0, chip.delay_timer);
+        assert_eq!(0, chip.sound_timer); // does not underflow
+    }
+    #[test]
+    fn test_tick_does_not_decrement_timers() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.delay_timer = 8;
+        chip.sound_timer = 8;
+        chip.ram[0x200] = 0x60; // V0 = 0x00
+        chip.ram[0x201] = 0x00;
+
+        chip.tick().unwrap();
+        assert_eq!(8, chip.delay_timer);
+        assert_eq!(8, chip.sound_timer);
+    }
+    #[test]
+    fn test_run_frame_executes_cpu_hz_divided_by_timer_hz_instructions() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.set_cpu_hz(600);
+        for addr in (0x200..0x400).step_by(2) {
+            chip.ram[addr] = 0x70; // V0 += 1
+            chip.ram[addr + 1] = 0x01;
+        }
+        chip.delay_timer = 8;
+
+        chip.run_frame().unwrap();
+        assert_eq!(10, chip.registers[0]);
+        assert_eq!(0x200 + 20, chip.pc);
+        assert_eq!(7, chip.delay_timer);
+    }
+    #[test]
+    fn test_run_frame_carries_over_remainder() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.set_cpu_hz(90); // 1.5 instructions per frame
+        for addr in (0x200..0x400).step_by(2) {
+            chip.ram[addr] = 0x70; // V0 += 1
+            chip.ram[addr + 1] = 0x01;
+        }
+
+        chip.run_frame().unwrap();
+        assert_eq!(1, chip.registers[0]);
+        chip.run_frame().unwrap();
+        assert_eq!(3, chip.registers[0]);
+        chip.run_frame().unwrap();
+        assert_eq!(4, chip.registers[0]);
+        chip.run_frame().unwrap();
+        assert_eq!(6, chip.registers[0]);
+    }
+    #[test]
+    fn test_run_frame_at_max_cpu_hz() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.set_cpu_hz(u32::MAX);
+        // DRW V0, V0, 1 waits for the vertical blank, so each frame runs one instruction
+        chip.load(&[0xD0, 0x01, 0x12, 0x00]).unwrap();
+
+        chip.run_frame().unwrap();
+        chip.run_frame().unwrap();
+        assert_eq!(0x202, chip.pc);
+    }
+    #[test]
+    fn test_sound_plays_until_sound_timer_reaches_zero() {
+        let mut chip = Chip8::new(Quirks::default());
+        assert!(!chip.is_sound_playing());
+
+        chip.registers[0] = 2;
+        chip.decode(0xF018).unwrap();
+        assert!(chip.is_sound_playing());
+
+        chip.tick_timers();
+        assert!(chip.is_sound_playing());
+        chip.tick_timers();
+        assert!(!chip.is_sound_playing());
+    }
+    #[test]
     fn test_fx1e_add_vx_to_i() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[5] = 8;
 
-        chip.decode(0xF51E);
+        chip.decode(0xF51E).unwrap();
         assert_eq!(8, chip.i);
     }
     #[test]
     fn test_fx55_store_registers_in_ram() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[0] = 8;
         chip.registers[1] = 6;
         chip.registers[2] = 5;
@@ -943,7 +2146,7 @@ mod tests {
         assert_eq!(0, chip.ram[6]);
         assert_eq!(0, chip.ram[7]);
         assert_eq!(0, chip.ram[8]);
-        chip.decode(0xF355); // VX = 3, store 0..=3
+        chip.decode(0xF355).unwrap(); // VX = 3, store 0..=3
         assert_eq!(0, chip.ram[3]);
         assert_eq!(8, chip.ram[4]);
         assert_eq!(6, chip.ram[5]);
@@ -953,7 +2156,7 @@ mod tests {
     }
     #[test]
     fn test_fx65_fill_registers_from_ram() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.ram[3] = 0;
         chip.ram[4] = 8;
         chip.ram[5] = 6;
@@ -967,7 +2170,7 @@ mod tests {
         assert_eq!(0, chip.registers[2]);
         assert_eq!(0, chip.registers[3]);
         assert_eq!(0, chip.registers[4]);
-        chip.decode(0xF365); // VX = 3, fill 0..=3
+        chip.decode(0xF365).unwrap(); // VX = 3, fill 0..=3
         assert_eq!(8, chip.registers[0]);
         assert_eq!(6, chip.registers[1]);
         assert_eq!(5, chip.registers[2]);
@@ -975,78 +2178,442 @@ mod tests {
         assert_eq!(0, chip.registers[4]);
     }
     #[test]
+    fn test_fx55_fx65_increment_i() {
+        let cases = [
+            (Quirks::COSMAC_VIP, 0x8),
+            (Quirks::CHIP_48, 0x7),
+            (Quirks::SUPER_CHIP, 0x4),
+        ];
+        for (quirks, expected_i) in cases {
+            let mut chip = Chip8::new(quirks);
+            chip.i = 0x4;
+            chip.decode(0xF355).unwrap();
+            assert_eq!(expected_i, chip.i);
+
+            chip.i = 0x4;
+            chip.decode(0xF365).unwrap();
+            assert_eq!(expected_i, chip.i);
+        }
+    }
+    #[test]
+    fn test_fx55_fx65_increment_i_wraps_around() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        // LD I, LONG #FFFF; LD [I], V0; LD I, LONG #FFFF; LD V0, [I]
+        chip.load(&[
+            0xF0, 0x00, 0xFF, 0xFF, 0xF0, 0x55, 0xF0, 0x00, 0xFF, 0xFF, 0xF0, 0x65,
+        ])
+        .unwrap();
+        chip.registers[0] = 0x42;
+
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!(0x42, chip.ram[0xFFFF]);
+        assert_eq!(0x0000, chip.i);
+
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!(0x0000, chip.i);
+    }
+    #[test]
     fn test_fx29_set_i_to_location_of_sprite_in_vx() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
 
         chip.registers[5] = 0x0; // character `0` starts at 0x50 (80)
-        chip.decode(0xF529);
+        chip.decode(0xF529).unwrap();
         assert_eq!(0x50, chip.i);
 
         chip.registers[6] = 0xA; // character `A` starts at 0x82 (130)
-        chip.decode(0xF629);
+        chip.decode(0xF629).unwrap();
         assert_eq!(0x82, chip.i);
     }
     #[test]
+    fn test_fx30_set_i_to_location_of_big_sprite_in_vx() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+
+        chip.registers[5] = 0x0;
+        chip.decode(0xF530).unwrap();
+        assert_eq!(0xA0, chip.i);
+
+        chip.registers[6] = 0x9;
+        chip.decode(0xF630).unwrap();
+        assert_eq!(0xA0 + 90, chip.i);
+        assert_eq!(
+            [0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF],
+            chip.ram[chip.i as usize..chip.i as usize + 10]
+        );
+    }
+    #[test]
+    fn test_fx75_fx85_save_and_restore_rpl_flags() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.registers[0] = 1;
+        chip.registers[1] = 2;
+        chip.registers[2] = 3;
+
+        chip.decode(0xF175).unwrap(); // save V0..=V1
+        chip.registers = [0; NUMBER_OF_REGISTERS];
+
+        chip.decode(0xF285).unwrap(); // restore V0..=V2
+        assert_eq!([1, 2, 0], chip.registers[0..3]);
+    }
+    #[test]
     fn test_fx33_binary_code_decimal_stored_in_vx() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[5] = 123;
 
         assert_eq!(0, chip.ram[chip.i as usize]);
         assert_eq!(0, chip.ram[chip.i as usize + 1]);
         assert_eq!(0, chip.ram[chip.i as usize + 2]);
-        chip.decode(0xF533);
+        chip.decode(0xF533).unwrap();
         assert_eq!(1, chip.ram[chip.i as usize]);
         assert_eq!(2, chip.ram[chip.i as usize + 1]);
         assert_eq!(3, chip.ram[chip.i as usize + 2]);
     }
     #[test]
+    fn test_fx33_out_of_bounds_is_an_error() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.registers[5] = 123;
+        chip.i = 0xFFFF;
+
+        assert_eq!(
+            Err(Chip8Error::MemoryAccessOutOfBounds {
+                address: 0x10000,
+                pc: 0x200
+            }),
+            chip.decode(0xF533)
+        );
+    }
+    #[test]
     fn test_ex9e_skip_if_vx_button_is_pressed() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[5] = 0x2;
 
         let pc = chip.pc;
         chip.keypad[0x2] = false; // simulate that key is not pressed
-        chip.decode(0xE59E);
+        chip.decode(0xE59E).unwrap();
         assert_eq!(pc, chip.pc); // pc is incremented in tick, not decode
 
         let pc = chip.pc;
         chip.keypad[0x2] = true; // simulate that key is pressed
-        chip.decode(0xE59E);
+        chip.decode(0xE59E).unwrap();
         assert_eq!(pc + 2, chip.pc);
     }
     #[test]
     fn test_exa1_skip_if_vx_button_is_not_pressed() {
-        let mut chip = Chip8::new();
+        let mut chip = Chip8::new(Quirks::default());
         chip.registers[5] = 0x2;
 
         let pc = chip.pc;
         chip.keypad[0x2] = true; // simulate that key is pressed
-        chip.decode(0xE5A1);
+        chip.decode(0xE5A1).unwrap();
         assert_eq!(pc, chip.pc); // pc is incremented in tick, not decode
 
         let pc = chip.pc;
         chip.keypad[0x2] = false; // simulate that key is not pressed
-        chip.decode(0xE5A1);
+        chip.decode(0xE5A1).unwrap();
         assert_eq!(pc + 2, chip.pc);
     }
     #[test]
-    fn test_fx0a_wait_for_key_to_be_pressed() {
-        let mut chip = Chip8::new();
-        chip.registers[5] = 0x2;
-        chip.keypad[0x2] = false; // simulate that key is not pressed
+    fn test_fx0a_wait_for_key_to_be_pressed_and_released() {
+        let mut chip = Chip8::new(Quirks::default());
         chip.ram[0x200] = 0xF5;
         chip.ram[0x200 + 1] = 0x0A;
 
         assert_eq!(0x200, chip.pc);
-        chip.tick();
+        chip.tick().unwrap();
         assert_eq!(0x200, chip.pc);
-        chip.tick();
-        assert_eq!(0x200, chip.pc);
-        chip.tick();
+        chip.tick().unwrap();
         assert_eq!(0x200, chip.pc);
 
-        chip.keypad[0x2] = true; // simulate that key is pressed
-        chip.tick();
+        chip.set_key(0x2, true).unwrap();
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        assert_eq!(0x200, chip.pc); // still held
+
+        chip.tick_timers();
+        chip.set_key(0x2, false).unwrap();
+        chip.tick().unwrap();
         assert_eq!(0x200 + 2, chip.pc);
+        assert_eq!(0x2, chip.registers[5]);
+    }
+    #[test]
+    fn test_fx0a_key_down_before_waiting_counts() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&[0xF5, 0x0A]).unwrap();
+        chip.set_key(0x7, true).unwrap();
+        chip.set_key(0x3, true).unwrap();
+        chip.run_frame().unwrap();
+        assert_eq!(0x200, chip.pc);
+
+        chip.set_key(0x7, false).unwrap();
+        chip.run_frame().unwrap();
+        assert_eq!(0x200, chip.pc); // waiting for the lowest key
+        chip.set_key(0x3, false).unwrap();
+        chip.tick().unwrap();
+        assert_eq!(0x202, chip.pc);
+        assert_eq!(0x3, chip.registers[5]);
+    }
+    #[test]
+    fn test_key_tap_is_held_until_vertical_blank() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&[0xE5, 0xA1, 0x71, 0x01, 0x12, 0x00]).unwrap(); // count instructions with key 5 down
+        chip.registers[5] = 0x5;
+        chip.input(KeypadInput {
+            down: 0,
+            pressed: 1 << 5,
+        });
+        chip.run_frame().unwrap();
+        assert!(chip.registers[1] > 0);
+        assert!(chip.get_keypad()[5]);
+
+        let count = chip.registers[1];
+        chip.run_frame().unwrap();
+        assert_eq!(count, chip.registers[1]);
+        assert!(!chip.get_keypad()[5]);
+    }
+    #[test]
+    fn test_fx0a_sees_key_released_and_pressed_again_in_one_frame() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&[0xF5, 0x0A, 0x12, 0x02]).unwrap();
+        let held = KeypadInput {
+            down: 1 << 7,
+            pressed: 0,
+        };
+        chip.input(held);
+        chip.run_frame().unwrap();
+        assert_eq!(0x200, chip.pc); // waiting for the release
+
+        // released and pressed again before the next frame
+        chip.input(KeypadInput {
+            pressed: 1 << 7,
+            ..held
+        });
+        chip.run_frame().unwrap();
+        assert_eq!(0x202, chip.pc);
+        assert_eq!(0x7, chip.registers[5]);
+        assert!(chip.get_keypad()[7]);
+    }
+    #[test]
+    fn test_set_key_queues_changes_only() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.set_key(0x1, false).unwrap();
+        chip.set_key(0x1, true).unwrap();
+        chip.set_key(0x1, true).unwrap();
+        chip.set_key(0x2, true).unwrap();
+        assert_eq!(VecDeque::from([(0x1, true), (0x2, true)]), chip.key_events);
+
+        chip.apply_key_events();
+        chip.set_key(0x1, false).unwrap();
+        chip.set_key(0x1, true).unwrap();
+        chip.apply_key_events();
+        assert!(chip.get_keypad()[0x1]);
+        assert_eq!(VecDeque::from([(0x1, false), (0x1, true)]), chip.key_events);
+    }
+    #[test]
+    fn test_set_key_and_set_ram_out_of_range() {
+        let mut chip = Chip8::new(Quirks::default());
+        assert_eq!(
+            Err(Chip8Error::InvalidKey {
+                key: NUMBER_OF_KEYS
+            }),
+            chip.set_key(NUMBER_OF_KEYS, true)
+        );
+        assert!(chip.key_events.is_empty());
+
+        assert_eq!(Ok(()), chip.set_ram(RAM_SIZE - 1, 0x42));
+        assert_eq!(0x42, chip.ram[RAM_SIZE - 1]);
+        assert_eq!(
+            Err(Chip8Error::AddressOutOfBounds { address: RAM_SIZE }),
+            chip.set_ram(RAM_SIZE, 0x42)
+        );
+    }
+
+    #[test]
+    fn test_tick_stops_at_breakpoint() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03]).unwrap();
+        chip.breakpoints_mut().add_breakpoint(0x204);
+
+        assert_eq!(None, chip.tick().unwrap());
+        assert_eq!(
+            Some(StopReason::Breakpoint { pc: 0x204 }),
+            chip.tick().unwrap()
+        );
+        assert_eq!(0x204, chip.pc);
+        assert_eq!(0, chip.registers[2]); // instruction at the breakpoint is not executed yet
+
+        assert_eq!(None, chip.tick().unwrap());
+        assert_eq!(3, chip.registers[2]);
+    }
+
+    #[test]
+    fn test_tick_stops_at_breakpoint_on_first_instruction() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&[0x60, 0x01, 0x61, 0x02]).unwrap();
+        chip.breakpoints_mut().add_breakpoint(0x200);
+
+        assert_eq!(
+            Some(StopReason::Breakpoint { pc: 0x200 }),
+            chip.tick().unwrap()
+        );
+        assert_eq!(0x200, chip.pc);
+        assert_eq!(0, chip.registers[0]);
+
+        assert_eq!(None, chip.tick().unwrap());
+        assert_eq!(1, chip.registers[0]);
+    }
+
+    #[test]
+    fn test_breakpoint_on_instruction_looping_on_itself_stops_once() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&[0x00, 0xE0, 0xF0, 0x0A]).unwrap();
+        chip.breakpoints_mut().add_breakpoint(0x202);
+
+        assert_eq!(
+            Some(StopReason::Breakpoint { pc: 0x202 }),
+            chip.tick().unwrap()
+        );
+        assert_eq!(None, chip.tick().unwrap());
+        assert_eq!(None, chip.tick().unwrap());
+    }
+
+    #[test]
+    fn test_run_frame_stops_at_breakpoint() {
+        let mut chip = Chip8::new(Quirks::default());
+        let program = assemble(
+            "
+            loop:
+                ADD V0, 1
+                JP loop
+            ",
+        );
+        chip.load(&program.unwrap()).unwrap();
+        chip.set_cpu_hz(600);
+        chip.delay_timer = 10;
+        chip.breakpoints_mut().add_breakpoint(0x202);
+
+        assert_eq!(
+            Some(StopReason::Breakpoint { pc: 0x202 }),
+            chip.run_frame().unwrap()
+        );
+        assert_eq!(1, chip.registers[0]);
+        assert_eq!(10, chip.delay_timer);
+    }
+
+    #[test]
+    fn test_tick_stops_on_register_condition() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&[0x73, 0x08, 0x12, 0x00]).unwrap(); // V3 += 8 forever
+        let condition = RegisterCondition {
+            register: 3,
+            comparison: Comparison::Equal,
+            value: 0x10,
+        };
+        chip.breakpoints_mut().add_condition(condition);
+
+        assert_eq!(None, chip.tick().unwrap());
+        assert_eq!(None, chip.tick().unwrap());
+        assert_eq!(Some(StopReason::Condition(condition)), chip.tick().unwrap());
+        assert_eq!(0x10, chip.registers[3]);
+    }
+
+    #[test]
+    fn test_tick_stops_on_memory_watchpoints() {
+        let mut chip = Chip8::new(Quirks::default());
+        let program = assemble(
+            "
+                LD I, #300
+                LD [I], V1
+                LD I, #300
+                LD V1, [I]
+            ",
+        );
+        chip.load(&program.unwrap()).unwrap();
+        chip.breakpoints_mut().add_watchpoint(Watchpoint {
+            addresses: 0x301..=0x301,
+            access: Access::ReadWrite,
+        });
+
+        assert_eq!(None, chip.tick().unwrap());
+        assert_eq!(
+            Some(StopReason::Watchpoint {
+                address: 0x301,
+                access: Access::Write,
+                pc: 0x202
+            }),
+            chip.tick().unwrap()
+        );
+        assert_eq!(None, chip.tick().unwrap());
+        assert_eq!(
+            Some(StopReason::Watchpoint {
+                address: 0x301,
+                access: Access::Read,
+                pc: 0x206
+            }),
+            chip.tick().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_save_and_load_state() {
+        let mut chip = Chip8::new(Quirks::XO_CHIP);
+        chip.load(&[0x00, 0xFF, 0xA2, 0x00, 0xD0, 0x00, 0x22, 0x00])
+            .unwrap();
+        for _ in 0..4 {
+            chip.tick().unwrap();
+        }
+        chip.registers[0xA] = 0x42;
+        chip.delay_timer = 30;
+        chip.keypad[0x5] = true;
+        chip.audio_buffer = Some([0xF0; AUDIO_PATTERN_SIZE]);
+        let state = chip.save_state();
+
+        let mut restored = Chip8::new(Quirks::XO_CHIP);
+        restored.load_state(&state).unwrap();
+        assert_eq!(chip.ram, restored.ram);
+        assert_eq!(chip.vram, restored.vram);
+        assert_eq!(0x200, restored.pc);
+        assert_eq!(chip.registers, restored.registers);
+        assert_eq!(0x200, restored.i);
+        assert_eq!(VecDeque::from([0x208]), restored.stack);
+        assert_eq!(chip.keypad, restored.keypad);
+        assert_eq!(30, restored.delay_timer);
+        assert!(restored.hires);
+        assert_eq!(chip.get_audio_pattern(), restored.get_audio_pattern());
+        assert_eq!(state, restored.save_state());
+    }
+
+    #[test]
+    fn test_load_state_restores_random_numbers() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.set_seed(7);
+        chip.load(&[0xC0, 0xFF, 0xC0, 0xFF]).unwrap();
+        chip.tick().unwrap();
+        let state = chip.save_state();
+        chip.tick().unwrap();
+        let number = chip.registers[0];
+
+        let mut restored = Chip8::new(Quirks::default());
+        restored.load_state(&state).unwrap();
+        restored.tick().unwrap();
+        assert_eq!(number, restored.registers[0]);
+        assert_eq!(Some(7), restored.get_seed());
+
+        // the same machine after it drew more numbers
+        chip.set_seed(8);
+        chip.load_state(&state).unwrap();
+        chip.tick().unwrap();
+        assert_eq!(number, chip.registers[0]);
+    }
+
+    #[test]
+    fn test_load_invalid_state_leaves_machine_untouched() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.registers[0] = 0x11;
+        let mut state = chip.save_state();
+        chip.registers[0] = 0x22;
+
+        state.pop();
+        assert_eq!(Err(SaveStateError::Truncated), chip.load_state(&state));
+        assert_eq!(0x22, chip.registers[0]);
     }
 }
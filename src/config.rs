@@ -0,0 +1,381 @@
+use crate::palette::Palette;
+use chip_8_rs::chip8::NUMBER_OF_KEYS;
+use std::path::{Path, PathBuf};
+use toml::{Table, Value};
+
+// settings from the config file, a missing file is the same as an empty one
+// top level settings go before the first table, they would belong to it otherwise
+//
+//   palette = "amber"
+//
+//   [keys]
+//   5 = ["W", "UP"]
+//
+//   [buttons]
+//   5 = "RIGHT_FACE_DOWN"
+//
+//   [palettes]
+//   night = ["#000020", "#8080FF"]
+//
+//   # only for the ROM with this hash, as printed on start
+//   [rom.9A3F0C21D4E5B687]
+//   palette = "night"
+//   [rom.9A3F0C21D4E5B687.keys]
+//   5 = "SPACE"
+#[derive(Debug, Default)]
+pub struct Config {
+    table: Table,
+}
+
+impl Config {
+    // $XDG_CONFIG_HOME/chip-8-rs/config.toml, ~/.config/chip-8-rs/config.toml
+    // or %APPDATA%\chip-8-rs\config.toml on Windows
+    pub fn default_path() -> Option<PathBuf> {
+        let directory = std::env::var_os("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("APPDATA").map(PathBuf::from))
+            .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".config")))?;
+        Some(directory.join("chip-8-rs").join("config.toml"))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        std::fs::read_to_string(path)
+            .map_err(|error| error.to_string())
+            .and_then(|text| text.parse())
+            .map_err(|error| format!("{}: {}", path.display(), error))
+    }
+
+    // table with the settings of the ROM with `rom_hash`
+    fn rom_table(&self, rom_hash: u64) -> Option<&Table> {
+        get_table(&self.table, &["rom", &format!("{:016X}", rom_hash)])
+    }
+
+    // keyboard keys of the ROM, [rom.<hash>.keys] overrides [keys] which overrides the default
+    pub fn keymap(&self, rom_hash: u64) -> Result<Keymap, String> {
+        self.bindings(rom_hash, "keys", Keymap::new(DEFAULT_KEYS))
+    }
+
+    // gamepad buttons of the ROM, [rom.<hash>.buttons] overrides [buttons] which overrides the
+    // default
+    pub fn gamepad_map(&self, rom_hash: u64) -> Result<Keymap, String> {
+        self.bindings(rom_hash, "buttons", Keymap::new(DEFAULT_BUTTONS))
+    }
+
+    // palette to start with, a name or colors like --palette, `palette` of [rom.<hash>] overrides
+    // the top level one
+    pub fn palette(&self, rom_hash: u64) -> Result<Option<String>, String> {
+        let value = self
+            .rom_table(rom_hash)
+            .and_then(|table| table.get("palette"))
+            .or_else(|| self.table.get("palette"));
+        value
+            .map(|value| palette_spec(value).map_err(|error| format!("palette: {}", error)))
+            .transpose()
+    }
+
+    // palettes of [palettes], F9 cycles through them after the presets
+    pub fn palettes(&self) -> Result<Vec<Palette>, String> {
+        let Some(table) = get_table(&self.table, &["palettes"]) else {
+            return Ok(Vec::new());
+        };
+        table
+            .iter()
+            .map(|(name, value)| {
+                let palette: Palette = palette_spec(value)?.parse()?;
+                Ok(Palette {
+                    name: name.clone(),
+                    ..palette
+                })
+            })
+            .collect::<Result<Vec<Palette>, String>>()
+            .map_err(|error| format!("[palettes]: {}", error))
+    }
+
+    fn bindings(&self, rom_hash: u64, section: &str, mut keymap: Keymap) -> Result<Keymap, String> {
+        for table in [Some(&self.table), self.rom_table(rom_hash)]
+            .into_iter()
+            .flatten()
+        {
+            if let Some(bindings) = get_table(table, &[section]) {
+                keymap
+                    .apply(bindings)
+                    .map_err(|error| format!("[{}]: {}", section, error))?;
+            }
+        }
+        Ok(keymap)
+    }
+}
+
+// table at the dotted `path`, None if any part of it is missing or not a table
+fn get_table<'a>(table: &'a Table, path: &[&str]) -> Option<&'a Table> {
+    path.iter()
+        .try_fold(table, |table, name| table.get(*name)?.as_table())
+}
+
+// a palette name or an array of colors, the latter joined like the colors of --palette
+fn palette_spec(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(name) => Ok(name.clone()),
+        Value::Array(values) => values
+            .iter()
+            .map(Value::as_str)
+            .collect::<Option<Vec<&str>>>()
+            .map(|colors| colors.join(","))
+            .ok_or_else(|| "colors must be strings".to_string()),
+        other => Err(format!(
+            "expected a name or an array of colors, not {}",
+            other.type_str()
+        )),
+    }
+}
+
+impl std::str::FromStr for Config {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Ok(Config {
+            table: text
+                .parse()
+                .map_err(|error: toml::de::Error| error.to_string())?,
+        })
+    }
+}
+
+// names of the keyboard keys or gamepad buttons bound to each CHIP-8 key, any of them presses it
+// names are raylib's without the KEY_ or GAMEPAD_BUTTON_ prefix (W, UP, KP_5, LEFT_FACE_UP...)
+// keys refer to their position on a US keyboard, so the default layout is the same block of keys
+// on AZERTY or QWERTZ keyboards, bind letters explicitly to follow another layout
+#[derive(Clone, Debug, PartialEq)]
+pub struct Keymap {
+    keys: [Vec<String>; NUMBER_OF_KEYS],
+}
+
+/*
+ * 1 2 3 C -> 1 2 3 4
+ * 4 5 6 D -> Q W E R
+ * 7 8 9 E -> A S D F
+ * A 0 B F -> Z X C V
+ */
+const DEFAULT_KEYS: [&[&str]; NUMBER_OF_KEYS] = [
+    &["X"],
+    &["ONE"],
+    &["TWO"],
+    &["THREE"],
+    &["Q"],
+    &["W"],
+    &["E"],
+    &["A"],
+    &["S"],
+    &["D"],
+    &["Z"],
+    &["C"],
+    &["FOUR"],
+    &["R"],
+    &["F"],
+    &["V"],
+];
+
+// D-pad on 2 4 6 8, which many games use as arrows, bottom face button on 5
+// other games need their own [rom.<hash>.buttons]
+const DEFAULT_BUTTONS: [&[&str]; NUMBER_OF_KEYS] = [
+    &[],
+    &[],
+    &["LEFT_FACE_UP"],
+    &[],
+    &["LEFT_FACE_LEFT"],
+    &["RIGHT_FACE_DOWN"],
+    &["LEFT_FACE_RIGHT"],
+    &[],
+    &["LEFT_FACE_DOWN"],
+    &[],
+    &[],
+    &[],
+    &[],
+    &[],
+    &[],
+    &[],
+];
+
+impl Keymap {
+    fn new(defaults: [&[&str]; NUMBER_OF_KEYS]) -> Self {
+        Keymap {
+            keys: defaults.map(|names| names.iter().map(|name| name.to_string()).collect()),
+        }
+    }
+
+    pub fn keys(&self, key: usize) -> &[String] {
+        &self.keys[key]
+    }
+
+    // bindings of every CHIP-8 key listed in `table` are replaced, the rest are kept
+    fn apply(&mut self, table: &Table) -> Result<(), String> {
+        for (key, value) in table {
+            let index = usize::from_str_radix(key, 16)
+                .ok()
+                .filter(|index| *index < NUMBER_OF_KEYS)
+                .ok_or_else(|| format!("invalid CHIP-8 key `{}`, keys are 0 to F", key))?;
+            let names = match value {
+                Value::String(name) => vec![name.clone()],
+                Value::Array(values) => values
+                    .iter()
+                    .map(|value| value.as_str().map(str::to_string))
+                    .collect::<Option<Vec<String>>>()
+                    .ok_or_else(|| format!("keys of `{}` must be strings", key))?,
+                other => {
+                    return Err(format!(
+                        "keys of `{}` must be a string or an array, not {}",
+                        key,
+                        other.type_str()
+                    ))
+                }
+            };
+            self.keys[index] = names.iter().map(|name| name.to_ascii_uppercase()).collect();
+        }
+        Ok(())
+    }
+
+    // turns key names into the frontend's keys, fails on the first unknown name
+    pub fn resolve<K>(
+        &self,
+        lookup: impl Fn(&str) -> Option<K>,
+    ) -> Result<[Vec<K>; NUMBER_OF_KEYS], String> {
+        let mut resolved: [Vec<K>; NUMBER_OF_KEYS] = Default::default();
+        for (bindings, names) in resolved.iter_mut().zip(&self.keys) {
+            for name in names {
+                bindings.push(lookup(name).ok_or_else(|| format!("unknown key `{}`", name))?);
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_keymap() {
+        let keymap = Config::default().keymap(0).unwrap();
+        assert_eq!(["X"], keymap.keys(0x0));
+        assert_eq!(["FOUR"], keymap.keys(0xC));
+        assert_eq!(["V"], keymap.keys(0xF));
+    }
+
+    #[test]
+    fn test_rom_keymap_overrides_user_keymap() {
+        let config: Config = "
+            [keys]
+            5 = [\"w\", \"UP\"]
+            a = \"LEFT\"
+            [rom.00000000000000FF.keys]
+            5 = \"SPACE\"
+        "
+        .parse()
+        .unwrap();
+
+        let keymap = config.keymap(0x1234).unwrap();
+        assert_eq!(["W", "UP"], keymap.keys(0x5));
+        assert_eq!(["LEFT"], keymap.keys(0xA));
+        assert_eq!(["E"], keymap.keys(0x6));
+
+        let keymap = config.keymap(0xFF).unwrap();
+        assert_eq!(["SPACE"], keymap.keys(0x5));
+        assert_eq!(["LEFT"], keymap.keys(0xA));
+    }
+
+    #[test]
+    fn test_gamepad_map() {
+        let config: Config = "
+            [buttons]
+            5 = []
+            [rom.00000000000000FF.buttons]
+            1 = [\"right_trigger_1\"]
+        "
+        .parse()
+        .unwrap();
+
+        let buttons = config.gamepad_map(0).unwrap();
+        assert_eq!(["LEFT_FACE_UP"], buttons.keys(0x2));
+        assert!(buttons.keys(0x5).is_empty());
+        assert!(buttons.keys(0x1).is_empty());
+        assert_eq!(
+            ["RIGHT_TRIGGER_1"],
+            config.gamepad_map(0xFF).unwrap().keys(0x1)
+        );
+    }
+
+    #[test]
+    fn test_keymap_errors() {
+        assert!("[keys\n5 = \"W\"".parse::<Config>().is_err());
+        let keymap = |text: &str| text.parse::<Config>().unwrap().keymap(0);
+        assert_eq!(
+            Err("[keys]: invalid CHIP-8 key `10`, keys are 0 to F".to_string()),
+            keymap("[keys]\n10 = \"A\"")
+        );
+        assert_eq!(
+            Err("[keys]: keys of `1` must be strings".to_string()),
+            keymap("[keys]\n1 = [1]")
+        );
+        assert_eq!(
+            Err("[keys]: keys of `1` must be a string or an array, not boolean".to_string()),
+            keymap("[keys]\n1 = true")
+        );
+    }
+
+    #[test]
+    fn test_palettes() {
+        let config: Config = "
+            palette = \"amber\"
+            [palettes]
+            night = [\"#000020\", \"#8080FF\"]
+            [rom.00000000000000FF]
+            palette = [\"#000000\", \"#FFFFFF\", \"#FF0000\", \"#00FF00\"]
+        "
+        .parse()
+        .unwrap();
+
+        assert_eq!(Ok(Some("amber".to_string())), config.palette(0));
+        assert_eq!(
+            Ok(Some("#000000,#FFFFFF,#FF0000,#00FF00".to_string())),
+            config.palette(0xFF)
+        );
+        let palettes = config.palettes().unwrap();
+        assert_eq!("night", palettes[0].name);
+        assert_eq!([0x80, 0x80, 0xFF], palettes[0].colors[1]);
+
+        assert_eq!(Ok(None), Config::default().palette(0));
+        // after a table header it belongs to the table
+        assert_eq!(
+            Ok(None),
+            "[keys]\npalette = \"amber\""
+                .parse::<Config>()
+                .unwrap()
+                .palette(0)
+        );
+        assert_eq!(
+            Err("palette: expected a name or an array of colors, not integer".to_string()),
+            "palette = 1".parse::<Config>().unwrap().palette(0)
+        );
+        assert_eq!(
+            Err("[palettes]: palette `#000000` needs 2 or 4 colors".to_string()),
+            "[palettes]\nmine = [\"#000000\"]"
+                .parse::<Config>()
+                .unwrap()
+                .palettes()
+        );
+    }
+
+    #[test]
+    fn test_resolve() {
+        let keymap = Keymap::new(DEFAULT_KEYS);
+        let resolved = keymap.resolve(|name| Some(name.len())).unwrap();
+        assert_eq!(vec![4], resolved[0xC]);
+        assert_eq!(
+            Err("unknown key `X`".to_string()),
+            keymap.resolve(|name| (name != "X").then_some(0))
+        );
+    }
+}
@@ -0,0 +1,260 @@
+use chip_8_rs::{Chip8, Chip8Error, Instruction, StopReason};
+use raylib::consts::KeyboardKey;
+use raylib::prelude::*;
+
+const FONT_SIZE: i32 = 10;
+const LINE_HEIGHT: i32 = 12;
+// instructions shown before and after PC
+const DISASSEMBLY_CONTEXT: usize = 6;
+const MEMORY_ROWS: usize = 16;
+const MEMORY_ROW_SIZE: usize = 8;
+
+/*
+ * TAB -> show/hide debugger, showing it pauses execution
+ * P   -> pause/continue
+ * N   -> execute single instruction while paused
+ * B   -> toggle breakpoint at PC
+ * N and B are disabled while a movie is recorded or replayed
+ */
+pub struct Debugger {
+    visible: bool,
+    paused: bool,
+    // why execution was last stopped by the core, shown until it continues
+    stop_reason: Option<StopReason>,
+    // set while a movie is recorded or replayed, movies only hold whole frames so stepping
+    // and breakpoints, which stop in the middle of one, are disabled
+    frames_only: bool,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Debugger {
+            visible: false,
+            paused: false,
+            stop_reason: None,
+            frames_only: false,
+        }
+    }
+
+    pub fn set_frames_only(&mut self, frames_only: bool) {
+        self.frames_only = frames_only;
+    }
+
+    // breakpoint triggered, open debugger on the instruction it stopped at
+    pub fn stop(&mut self, reason: StopReason) {
+        self.visible = true;
+        self.paused = true;
+        self.stop_reason = Some(reason);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn handle_input(
+        &mut self,
+        rl_handle: &RaylibHandle,
+        chip: &mut Chip8,
+    ) -> Result<(), Chip8Error> {
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_TAB) {
+            self.visible = !self.visible;
+            if self.visible {
+                self.paused = true;
+            }
+        }
+
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_P) {
+            self.paused = !self.paused;
+            self.stop_reason = None;
+        }
+
+        if self.frames_only {
+            return Ok(());
+        }
+
+        if rl_handle.is_key_pressed(KeyboardKey::KEY_B) {
+            let pc = chip.get_pc();
+            chip.breakpoints_mut().toggle_breakpoint(pc);
+        }
+
+        if self.paused && rl_handle.is_key_pressed(KeyboardKey::KEY_N) {
+            self.stop_reason = chip.tick()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn draw(&self, chip: &Chip8, renderer: &mut RaylibDrawHandle) {
+        if !self.visible {
+            return;
+        }
+
+        let width = renderer.get_screen_width();
+        let height = renderer.get_screen_height();
+        renderer.draw_rectangle(0, 0, width, height, Color::BLACK.fade(0.8));
+
+        let columns = [
+            (10, register_lines(chip)),
+            (200, disassembly_lines(chip)),
+            (380, memory_lines(chip)),
+        ];
+        for (x, lines) in columns {
+            for (row, line) in lines.iter().enumerate() {
+                let y = 10 + row as i32 * LINE_HEIGHT;
+                renderer.draw_text(line, x, y, FONT_SIZE, Color::GREEN);
+            }
+        }
+
+        let status = match (self.paused, &self.stop_reason) {
+            (true, _) if self.frames_only => "PAUSED  P: continue  TAB: hide".to_string(),
+            (false, _) if self.frames_only => "RUNNING  P: pause  TAB: hide".to_string(),
+            (true, Some(reason)) => format!("STOPPED: {}  P: continue  N: step", reason),
+            (true, None) => "PAUSED  P: continue  N: step  B: breakpoint  TAB: hide".to_string(),
+            (false, _) => "RUNNING  P: pause  B: breakpoint  TAB: hide".to_string(),
+        };
+        renderer.draw_text(
+            &status,
+            10,
+            height - LINE_HEIGHT - 4,
+            FONT_SIZE,
+            Color::YELLOW,
+        );
+    }
+}
+
+fn register_lines(chip: &Chip8) -> Vec<String> {
+    let registers = chip.get_registers();
+    let mut lines = vec![
+        format!("PC {:04X}  I {:04X}", chip.get_pc(), chip.get_i()),
+        format!(
+            "DT {:02X}    ST {:02X}",
+            chip.get_delay_timer(),
+            chip.get_sound_timer()
+        ),
+        String::new(),
+    ];
+
+    for row in 0..registers.len() / 2 {
+        lines.push(format!(
+            "V{:X} {:02X}    V{:X} {:02X}",
+            row,
+            registers[row],
+            row + 8,
+            registers[row + 8]
+        ));
+    }
+
+    lines.push(String::new());
+    lines.push(format!("STACK ({})", chip.get_stack().len()));
+    for address in chip.get_stack().iter().rev() {
+        lines.push(format!("  {:04X}", address));
+    }
+
+    lines
+}
+
+// instructions around PC, current instruction is marked with `>` and breakpoints with `*`
+fn disassembly_lines(chip: &Chip8) -> Vec<String> {
+    let ram = chip.get_ram();
+    let pc = chip.get_pc();
+    let start = pc.saturating_sub(DISASSEMBLY_CONTEXT * 2);
+    let end = pc + DISASSEMBLY_CONTEXT * 2;
+
+    (start..=end)
+        .step_by(2)
+        .filter(|address| address + 1 < ram.len())
+        .map(|address| {
+            let marker = if address == pc { '>' } else { ' ' };
+            let breakpoint = if chip.breakpoints().has_breakpoint(address) {
+                '*'
+            } else {
+                ' '
+            };
+            let opcode = (ram[address] as u16) << 8 | ram[address + 1] as u16;
+            let mnemonic = Instruction::parse(opcode)
+                .map(|instruction| instruction.to_string())
+                .unwrap_or_default();
+            format!(
+                "{}{} {:04X}  {:04X}  {}",
+                marker, breakpoint, address, opcode, mnemonic
+            )
+        })
+        .collect()
+}
+
+// hex view of ram starting at I
+fn memory_lines(chip: &Chip8) -> Vec<String> {
+    let ram = chip.get_ram();
+    let start = chip.get_i() as usize / MEMORY_ROW_SIZE * MEMORY_ROW_SIZE;
+
+    (0..MEMORY_ROWS)
+        .map(|row| start + row * MEMORY_ROW_SIZE)
+        .filter(|address| *address < ram.len())
+        .map(|address| {
+            let bytes: Vec<String> = ram[address..address + MEMORY_ROW_SIZE]
+                .iter()
+                .map(|byte| format!("{:02X}", byte))
+                .collect();
+            format!("{:04X}  {}", address, bytes.join(" "))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip_8_rs::Quirks;
+
+    fn chip_with_program(program: &[u8]) -> Chip8 {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(program).unwrap();
+        chip
+    }
+
+    #[test]
+    fn test_register_lines() {
+        let mut chip = chip_with_program(&[0x6A, 0x42, 0xA3, 0x00, 0x22, 0x08]);
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+        chip.tick().unwrap();
+
+        let lines = register_lines(&chip);
+        assert_eq!("PC 0208  I 0300", lines[0]);
+        assert_eq!("V2 00    VA 42", lines[5]);
+        assert_eq!("STACK (1)", lines[12]);
+        assert_eq!("  0206", lines[13]);
+    }
+
+    #[test]
+    fn test_disassembly_lines_mark_pc() {
+        let mut chip = chip_with_program(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03]);
+        chip.tick().unwrap();
+
+        let lines = disassembly_lines(&chip);
+        assert_eq!(2 * DISASSEMBLY_CONTEXT + 1, lines.len());
+        assert_eq!("   0200  6001  LD V0, #01", lines[DISASSEMBLY_CONTEXT - 1]);
+        assert_eq!(">  0202  6102  LD V1, #02", lines[DISASSEMBLY_CONTEXT]);
+    }
+
+    #[test]
+    fn test_disassembly_lines_mark_breakpoints() {
+        let mut chip = chip_with_program(&[0x60, 0x01, 0x61, 0x02, 0x62, 0x03]);
+        chip.breakpoints_mut().add_breakpoint(0x202);
+        chip.breakpoints_mut().add_breakpoint(0x204);
+        chip.tick().unwrap();
+
+        let lines = disassembly_lines(&chip);
+        assert_eq!(">* 0202  6102  LD V1, #02", lines[DISASSEMBLY_CONTEXT]);
+        assert_eq!(" * 0204  6203  LD V2, #03", lines[DISASSEMBLY_CONTEXT + 1]);
+    }
+
+    #[test]
+    fn test_memory_lines_start_at_i() {
+        let mut chip = chip_with_program(&[0xA2, 0x03]);
+        chip.tick().unwrap();
+
+        let lines = memory_lines(&chip);
+        assert_eq!(MEMORY_ROWS, lines.len());
+        assert_eq!("0200  A2 03 00 00 00 00 00 00", lines[0]);
+    }
+}
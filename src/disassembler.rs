@@ -0,0 +1,290 @@
+use crate::chip8::PROGRAM_START;
+use crate::instruction::Instruction;
+use std::collections::BTreeMap;
+
+// data bytes per DB line
+const BYTES_PER_LINE: usize = 8;
+// column where the address/opcode comment starts
+const COMMENT_COLUMN: usize = 28;
+
+// what an address is used as, decides the label name
+// a subroutine that is also jumped to is still called a subroutine
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,
+    Code,
+    Subroutine,
+}
+
+// ROM split into code and data
+// code is everything reachable from 0x200 by following jumps, calls and skips,
+// the rest (sprites, tables, code only reachable through BNNN) is data
+struct Analysis<'a> {
+    rom: &'a [u8],
+    // indexed by offset into the ROM, true where an instruction starts
+    instruction_starts: Vec<bool>,
+    labels: BTreeMap<usize, LabelKind>,
+}
+
+impl<'a> Analysis<'a> {
+    fn new(rom: &'a [u8]) -> Self {
+        let mut analysis = Analysis {
+            rom,
+            instruction_starts: vec![false; rom.len()],
+            labels: BTreeMap::new(),
+        };
+        analysis.trace(PROGRAM_START);
+        analysis.remove_hidden_labels();
+        analysis
+    }
+
+    fn contains(&self, address: usize) -> bool {
+        (PROGRAM_START..PROGRAM_START + self.rom.len()).contains(&address)
+    }
+
+    fn word(&self, address: usize) -> Option<u16> {
+        if self.contains(address) && self.contains(address + 1) {
+            let offset = address - PROGRAM_START;
+            Some((self.rom[offset] as u16) << 8 | self.rom[offset + 1] as u16)
+        } else {
+            None
+        }
+    }
+
+    fn add_label(&mut self, address: usize, kind: LabelKind) {
+        if self.contains(address) {
+            let label = self.labels.entry(address).or_insert(kind);
+            *label = (*label).max(kind);
+        }
+    }
+
+    fn trace(&mut self, start: usize) {
+        let mut pending = vec![start];
+
+        while let Some(address) = pending.pop() {
+            let Some(instruction) = self.word(address).and_then(Instruction::parse) else {
+                continue;
+            };
+            let offset = address - PROGRAM_START;
+            if self.instruction_starts[offset] {
+                continue;
+            }
+            self.instruction_starts[offset] = true;
+
+            let next = address + instruction.size();
+            match instruction {
+                Instruction::Jump(nnn) => {
+                    self.add_label(nnn as usize, LabelKind::Code);
+                    pending.push(nnn as usize);
+                }
+                Instruction::Call(nnn) => {
+                    self.add_label(nnn as usize, LabelKind::Subroutine);
+                    pending.push(nnn as usize);
+                    pending.push(next);
+                }
+                Instruction::Return | Instruction::Exit => {}
+                // jump tables can't be followed without knowing VX
+                Instruction::JumpWithOffset(_, nnn) => {
+                    self.add_label(nnn as usize, LabelKind::Code);
+                }
+                Instruction::SkipIfEqualByte(..)
+                | Instruction::SkipIfNotEqualByte(..)
+                | Instruction::SkipIfEqual(..)
+                | Instruction::SkipIfNotEqual(..)
+                | Instruction::SkipIfKey(_)
+                | Instruction::SkipIfNotKey(_) => {
+                    // skipping over F000 NNNN skips 4 bytes
+                    let skipped = match self.word(next) {
+                        Some(0xF000) => 4,
+                        _ => 2,
+                    };
+                    pending.push(next);
+                    pending.push(next + skipped);
+                }
+                Instruction::SetI(nnn) => {
+                    self.add_label(nnn as usize, LabelKind::Data);
+                    pending.push(next);
+                }
+                Instruction::SetILong => {
+                    if let Some(nnnn) = self.word(address + 2) {
+                        self.add_label(nnnn as usize, LabelKind::Data);
+                    }
+                    pending.push(next);
+                }
+                _ => pending.push(next),
+            }
+        }
+    }
+
+    // the listing puts all bytes of an instruction on its line, so an address inside of one (I
+    // pointing at its second byte, a jump into its middle) has no line for the label to go on
+    fn remove_hidden_labels(&mut self) {
+        let mut hidden = vec![false; self.rom.len()];
+        let mut offset = 0;
+        while offset < self.rom.len() {
+            if self.instruction_starts[offset] {
+                let size = self
+                    .word(PROGRAM_START + offset)
+                    .and_then(Instruction::parse)
+                    .map_or(1, |instruction| instruction.size());
+                let end = (offset + size).min(self.rom.len());
+                hidden[offset + 1..end].fill(true);
+                offset = end;
+            } else {
+                offset += 1;
+            }
+        }
+        self.labels
+            .retain(|address, _| !hidden[address - PROGRAM_START]);
+    }
+
+    fn label(&self, address: usize) -> Option<String> {
+        self.labels.get(&address).map(|kind| match kind {
+            LabelKind::Data => format!("data_{:03X}", address),
+            LabelKind::Code => format!("label_{:03X}", address),
+            LabelKind::Subroutine => format!("sub_{:03X}", address),
+        })
+    }
+
+    fn operand(&self, address: u16) -> String {
+        self.label(address as usize)
+            .unwrap_or_else(|| format!("#{:03X}", address))
+    }
+}
+
+fn listing_line(text: &str, comment: &str) -> String {
+    format!(
+        "    {:<width$}; {}\n",
+        text,
+        comment,
+        width = COMMENT_COLUMN - 4
+    )
+}
+
+// annotated listing of the ROM, in the same syntax Instruction is displayed in
+// every line has the address (and the opcode for instructions) in a comment
+pub fn disassemble(rom: &[u8]) -> String {
+    let analysis = Analysis::new(rom);
+    let mut listing = String::new();
+    let mut offset = 0;
+
+    while offset < rom.len() {
+        let address = PROGRAM_START + offset;
+        if let Some(label) = analysis.label(address) {
+            listing.push_str(&format!("{}:\n", label));
+        }
+
+        if analysis.instruction_starts[offset] {
+            let opcode = analysis.word(address).unwrap_or_default();
+            let instruction = Instruction::parse(opcode).expect("traced instructions are valid");
+            let mut text = instruction.mnemonic(|nnn| analysis.operand(nnn));
+            let mut comment = format!("{:03X}  {:04X}", address, opcode);
+            if instruction == Instruction::SetILong {
+                let nnnn = analysis.word(address + 2).unwrap_or_default();
+                text.push_str(&format!(" {}", analysis.operand(nnnn)));
+                comment.push_str(&format!(" {:04X}", nnnn));
+            }
+            listing.push_str(&listing_line(&text, &comment));
+            offset += instruction.size();
+        } else {
+            // data runs until the next instruction or label, BYTES_PER_LINE at most
+            let mut end = offset + 1;
+            while end < rom.len()
+                && end - offset < BYTES_PER_LINE
+                && !analysis.instruction_starts[end]
+                && analysis.label(PROGRAM_START + end).is_none()
+            {
+                end += 1;
+            }
+
+            let bytes: Vec<String> = rom[offset..end]
+                .iter()
+                .map(|byte| format!("#{:02X}", byte))
+                .collect();
+            let text = format!("DB {}", bytes.join(", "));
+            listing.push_str(&listing_line(&text, &format!("{:03X}", address)));
+            offset = end;
+        }
+    }
+
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disassemble_follows_calls_and_jumps() {
+        let rom = [
+            0x22, 0x06, // 200: CALL sub_206
+            0x12, 0x02, // 202: JP label_202
+            0xFF, 0xFF, // 204: unreachable
+            0x00, 0xE0, // 206: CLS
+            0x00, 0xEE, // 208: RET
+        ];
+
+        let listing = disassemble(&rom);
+        let lines: Vec<&str> = listing.lines().collect();
+        assert_eq!(
+            vec![
+                "    CALL sub_206            ; 200  2206",
+                "label_202:",
+                "    JP label_202            ; 202  1202",
+                "    DB #FF, #FF             ; 204",
+                "sub_206:",
+                "    CLS                     ; 206  00E0",
+                "    RET                     ; 208  00EE",
+            ],
+            lines
+        );
+    }
+
+    #[test]
+    fn test_disassemble_sprite_data() {
+        let rom = [
+            0xA2, 0x06, // 200: LD I, data_206
+            0xD0, 0x13, // 202: DRW V0, V1, 3
+            0x00, 0xFD, // 204: EXIT
+            0x3C, 0x42, 0x3C, // 206: sprite
+        ];
+
+        let listing = disassemble(&rom);
+        assert!(listing.contains("    LD I, data_206          ; 200  A206\n"));
+        assert!(listing.contains("data_206:\n    DB #3C, #42, #3C        ; 206\n"));
+    }
+
+    #[test]
+    fn test_disassemble_address_inside_instruction_has_no_label() {
+        let rom = [
+            0xA2, 0x01, // 200: LD I, #201
+            0x00, 0xFD, // 202: EXIT
+        ];
+
+        let listing = disassemble(&rom);
+        assert_eq!(
+            "    LD I, #201              ; 200  A201\n    EXIT                    ; 202  00FD\n",
+            listing
+        );
+    }
+
+    #[test]
+    fn test_disassemble_skips_are_followed_both_ways() {
+        let rom = [
+            0x30, 0x01, // 200: SE V0, #01
+            0x00, 0xFD, // 202: EXIT
+            0xF0, 0x00, 0x02, 0x0A, // 204: LD I, LONG data_20A
+            0x00, 0xFD, // 208: EXIT
+            0xAB, // 20A: data
+        ];
+
+        let listing = disassemble(&rom);
+        assert!(listing.contains("    LD I, LONG data_20A     ; 204  F000 020A\n"));
+        assert!(listing.contains("data_20A:\n    DB #AB"));
+    }
+
+    #[test]
+    fn test_disassemble_odd_sized_rom() {
+        assert_eq!("    DB #12                  ; 200\n", disassemble(&[0x12]));
+    }
+}
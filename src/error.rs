@@ -0,0 +1,70 @@
+use std::fmt;
+
+// faults the emulated program (or the ROM itself) can cause, and invalid input from the frontend
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Chip8Error {
+    // ROM does not fit in memory above 0x200
+    RomTooLarge { size: usize, max: usize },
+    // 00EE executed with an empty stack
+    StackUnderflow { address: usize },
+    // 2NNN executed with a full stack
+    StackOverflow { address: usize },
+    // PC points outside of program memory
+    PcOutOfBounds { pc: usize },
+    // instruction at `pc` tried to read or write memory at `address` which does not exist
+    MemoryAccessOutOfBounds { address: usize, pc: usize },
+    UnknownOpcode { opcode: u16, address: usize },
+    // set_key with a key above 0xF
+    InvalidKey { key: usize },
+    // set_ram with an address outside of memory
+    AddressOutOfBounds { address: usize },
+}
+
+impl fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Chip8Error::RomTooLarge { size, max } => {
+                write!(
+                    f,
+                    "ROM is too large: {} bytes, at most {} fit in memory",
+                    size, max
+                )
+            }
+            Chip8Error::StackUnderflow { address } => {
+                write!(
+                    f,
+                    "stack underflow at {:#05X}: return with empty stack",
+                    address
+                )
+            }
+            Chip8Error::StackOverflow { address } => {
+                write!(
+                    f,
+                    "stack overflow at {:#05X}: too many nested calls",
+                    address
+                )
+            }
+            Chip8Error::PcOutOfBounds { pc } => {
+                write!(f, "PC out of bounds: {:#05X}", pc)
+            }
+            Chip8Error::MemoryAccessOutOfBounds { address, pc } => {
+                write!(
+                    f,
+                    "memory access out of bounds at {:#05X}: address {:#06X}",
+                    pc, address
+                )
+            }
+            Chip8Error::UnknownOpcode { opcode, address } => {
+                write!(f, "unknown opcode {:04X} at {:#05X}", opcode, address)
+            }
+            Chip8Error::InvalidKey { key } => {
+                write!(f, "invalid key {:X}, keys are 0 to F", key)
+            }
+            Chip8Error::AddressOutOfBounds { address } => {
+                write!(f, "address {:#06X} is outside of memory", address)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Chip8Error {}
@@ -0,0 +1,393 @@
+use std::fmt;
+
+// every instruction of CHIP-8, SUPER-CHIP 1.1 and XO-CHIP
+// X and Y are register numbers, N is a nibble, NN a byte and NNN an address
+// this is the only opcode table, Chip8::decode and the disassembler both go through `parse`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+    // 00E0
+    Clear,
+    // 00EE
+    Return,
+    // 00CN
+    ScrollDown(u8),
+    // 00DN
+    ScrollUp(u8),
+    // 00FB
+    ScrollRight,
+    // 00FC
+    ScrollLeft,
+    // 00FD
+    Exit,
+    // 00FE
+    LowRes,
+    // 00FF
+    HighRes,
+    // 0NNN
+    System(u16),
+    // 1NNN
+    Jump(u16),
+    // 2NNN
+    Call(u16),
+    // 3XNN
+    SkipIfEqualByte(u8, u8),
+    // 4XNN
+    SkipIfNotEqualByte(u8, u8),
+    // 5XY0
+    SkipIfEqual(u8, u8),
+    // 5XY2
+    StoreRange(u8, u8),
+    // 5XY3
+    LoadRange(u8, u8),
+    // 6XNN
+    SetByte(u8, u8),
+    // 7XNN
+    AddByte(u8, u8),
+    // 8XY0
+    Set(u8, u8),
+    // 8XY1
+    Or(u8, u8),
+    // 8XY2
+    And(u8, u8),
+    // 8XY3
+    Xor(u8, u8),
+    // 8XY4
+    Add(u8, u8),
+    // 8XY5
+    Sub(u8, u8),
+    // 8XY6
+    ShiftRight(u8, u8),
+    // 8XY7
+    SubReverse(u8, u8),
+    // 8XYE
+    ShiftLeft(u8, u8),
+    // 9XY0
+    SkipIfNotEqual(u8, u8),
+    // ANNN
+    SetI(u16),
+    // BNNN, X is only used with the jump quirk
+    JumpWithOffset(u8, u16),
+    // CXNN
+    Random(u8, u8),
+    // DXYN
+    Draw(u8, u8, u8),
+    // EX9E
+    SkipIfKey(u8),
+    // EXA1
+    SkipIfNotKey(u8),
+    // F000 NNNN, the address is stored in the next two bytes
+    SetILong,
+    // FN01
+    Planes(u8),
+    // F002
+    Audio,
+    // FX07
+    GetDelay(u8),
+    // FX0A
+    WaitForKey(u8),
+    // FX15
+    SetDelay(u8),
+    // FX18
+    SetSound(u8),
+    // FX1E
+    AddI(u8),
+    // FX29
+    Font(u8),
+    // FX30
+    BigFont(u8),
+    // FX33
+    Bcd(u8),
+    // FX3A
+    Pitch(u8),
+    // FX55
+    Store(u8),
+    // FX65
+    Load(u8),
+    // FX75
+    StoreFlags(u8),
+    // FX85
+    LoadFlags(u8),
+}
+
+impl Instruction {
+    pub fn parse(opcode: u16) -> Option<Instruction> {
+        let nibbles = (
+            ((opcode & 0xF000) >> 12) as u8,
+            ((opcode & 0x0F00) >> 8) as u8,
+            ((opcode & 0x00F0) >> 4) as u8,
+            (opcode & 0x000F) as u8,
+        );
+
+        let nnn = opcode & 0x0FFF;
+        let nn = (opcode & 0x00FF) as u8;
+
+        let instruction = match nibbles {
+            (0x0, 0x0, 0xE, 0x0) => Instruction::Clear,
+            (0x0, 0x0, 0xE, 0xE) => Instruction::Return,
+            (0x0, 0x0, 0xC, n) => Instruction::ScrollDown(n),
+            (0x0, 0x0, 0xD, n) => Instruction::ScrollUp(n),
+            (0x0, 0x0, 0xF, 0xB) => Instruction::ScrollRight,
+            (0x0, 0x0, 0xF, 0xC) => Instruction::ScrollLeft,
+            (0x0, 0x0, 0xF, 0xD) => Instruction::Exit,
+            (0x0, 0x0, 0xF, 0xE) => Instruction::LowRes,
+            (0x0, 0x0, 0xF, 0xF) => Instruction::HighRes,
+            (0x0, _, _, _) => Instruction::System(nnn),
+            (0x1, _, _, _) => Instruction::Jump(nnn),
+            (0x2, _, _, _) => Instruction::Call(nnn),
+            (0x3, x, _, _) => Instruction::SkipIfEqualByte(x, nn),
+            (0x4, x, _, _) => Instruction::SkipIfNotEqualByte(x, nn),
+            (0x5, x, y, 0) => Instruction::SkipIfEqual(x, y),
+            (0x5, x, y, 2) => Instruction::StoreRange(x, y),
+            (0x5, x, y, 3) => Instruction::LoadRange(x, y),
+            (0x6, x, _, _) => Instruction::SetByte(x, nn),
+            (0x7, x, _, _) => Instruction::AddByte(x, nn),
+            (0x8, x, y, 0) => Instruction::Set(x, y),
+            (0x8, x, y, 1) => Instruction::Or(x, y),
+            (0x8, x, y, 2) => Instruction::And(x, y),
+            (0x8, x, y, 3) => Instruction::Xor(x, y),
+            (0x8, x, y, 4) => Instruction::Add(x, y),
+            (0x8, x, y, 5) => Instruction::Sub(x, y),
+            (0x8, x, y, 6) => Instruction::ShiftRight(x, y),
+            (0x8, x, y, 7) => Instruction::SubReverse(x, y),
+            (0x8, x, y, 0xE) => Instruction::ShiftLeft(x, y),
+            (0x9, x, y, 0) => Instruction::SkipIfNotEqual(x, y),
+            (0xA, _, _, _) => Instruction::SetI(nnn),
+            (0xB, x, _, _) => Instruction::JumpWithOffset(x, nnn),
+            (0xC, x, _, _) => Instruction::Random(x, nn),
+            (0xD, x, y, n) => Instruction::Draw(x, y, n),
+            (0xE, x, 9, 0xE) => Instruction::SkipIfKey(x),
+            (0xE, x, 0xA, 1) => Instruction::SkipIfNotKey(x),
+            (0xF, 0, 0, 0) => Instruction::SetILong,
+            (0xF, n, 0, 1) => Instruction::Planes(n),
+            (0xF, 0, 0, 2) => Instruction::Audio,
+            (0xF, x, 0, 7) => Instruction::GetDelay(x),
+            (0xF, x, 0, 0xA) => Instruction::WaitForKey(x),
+            (0xF, x, 1, 5) => Instruction::SetDelay(x),
+            (0xF, x, 1, 8) => Instruction::SetSound(x),
+            (0xF, x, 1, 0xE) => Instruction::AddI(x),
+            (0xF, x, 2, 9) => Instruction::Font(x),
+            (0xF, x, 3, 0) => Instruction::BigFont(x),
+            (0xF, x, 3, 3) => Instruction::Bcd(x),
+            (0xF, x, 3, 0xA) => Instruction::Pitch(x),
+            (0xF, x, 5, 5) => Instruction::Store(x),
+            (0xF, x, 6, 5) => Instruction::Load(x),
+            (0xF, x, 7, 5) => Instruction::StoreFlags(x),
+            (0xF, x, 8, 5) => Instruction::LoadFlags(x),
+            _ => return None,
+        };
+
+        Some(instruction)
+    }
+
+    // inverse of parse
+    pub fn encode(&self) -> u16 {
+        let xy = |opcode: u16, x: u8, y: u8| opcode | (x as u16) << 8 | (y as u16) << 4;
+        let xnn = |opcode: u16, x: u8, nn: u8| opcode | (x as u16) << 8 | nn as u16;
+
+        match *self {
+            Instruction::Clear => 0x00E0,
+            Instruction::Return => 0x00EE,
+            Instruction::ScrollDown(n) => 0x00C0 | n as u16,
+            Instruction::ScrollUp(n) => 0x00D0 | n as u16,
+            Instruction::ScrollRight => 0x00FB,
+            Instruction::ScrollLeft => 0x00FC,
+            Instruction::Exit => 0x00FD,
+            Instruction::LowRes => 0x00FE,
+            Instruction::HighRes => 0x00FF,
+            Instruction::System(nnn) => nnn,
+            Instruction::Jump(nnn) => 0x1000 | nnn,
+            Instruction::Call(nnn) => 0x2000 | nnn,
+            Instruction::SkipIfEqualByte(x, nn) => xnn(0x3000, x, nn),
+            Instruction::SkipIfNotEqualByte(x, nn) => xnn(0x4000, x, nn),
+            Instruction::SkipIfEqual(x, y) => xy(0x5000, x, y),
+            Instruction::StoreRange(x, y) => xy(0x5002, x, y),
+            Instruction::LoadRange(x, y) => xy(0x5003, x, y),
+            Instruction::SetByte(x, nn) => xnn(0x6000, x, nn),
+            Instruction::AddByte(x, nn) => xnn(0x7000, x, nn),
+            Instruction::Set(x, y) => xy(0x8000, x, y),
+            Instruction::Or(x, y) => xy(0x8001, x, y),
+            Instruction::And(x, y) => xy(0x8002, x, y),
+            Instruction::Xor(x, y) => xy(0x8003, x, y),
+            Instruction::Add(x, y) => xy(0x8004, x, y),
+            Instruction::Sub(x, y) => xy(0x8005, x, y),
+            Instruction::ShiftRight(x, y) => xy(0x8006, x, y),
+            Instruction::SubReverse(x, y) => xy(0x8007, x, y),
+            Instruction::ShiftLeft(x, y) => xy(0x800E, x, y),
+            Instruction::SkipIfNotEqual(x, y) => xy(0x9000, x, y),
+            Instruction::SetI(nnn) => 0xA000 | nnn,
+            // X is the top nibble of NNN
+            Instruction::JumpWithOffset(_, nnn) => 0xB000 | nnn,
+            Instruction::Random(x, nn) => xnn(0xC000, x, nn),
+            Instruction::Draw(x, y, n) => xy(0xD000, x, y) | n as u16,
+            Instruction::SkipIfKey(x) => xnn(0xE000, x, 0x9E),
+            Instruction::SkipIfNotKey(x) => xnn(0xE000, x, 0xA1),
+            Instruction::SetILong => 0xF000,
+            Instruction::Planes(n) => xnn(0xF000, n, 0x01),
+            Instruction::Audio => 0xF002,
+            Instruction::GetDelay(x) => xnn(0xF000, x, 0x07),
+            Instruction::WaitForKey(x) => xnn(0xF000, x, 0x0A),
+            Instruction::SetDelay(x) => xnn(0xF000, x, 0x15),
+            Instruction::SetSound(x) => xnn(0xF000, x, 0x18),
+            Instruction::AddI(x) => xnn(0xF000, x, 0x1E),
+            Instruction::Font(x) => xnn(0xF000, x, 0x29),
+            Instruction::BigFont(x) => xnn(0xF000, x, 0x30),
+            Instruction::Bcd(x) => xnn(0xF000, x, 0x33),
+            Instruction::Pitch(x) => xnn(0xF000, x, 0x3A),
+            Instruction::Store(x) => xnn(0xF000, x, 0x55),
+            Instruction::Load(x) => xnn(0xF000, x, 0x65),
+            Instruction::StoreFlags(x) => xnn(0xF000, x, 0x75),
+            Instruction::LoadFlags(x) => xnn(0xF000, x, 0x85),
+        }
+    }
+
+    // size in bytes, including the operand stored after F000
+    pub fn size(&self) -> usize {
+        match self {
+            Instruction::SetILong => 4,
+            _ => 2,
+        }
+    }
+
+    // address the instruction jumps to, calls or points I at
+    pub fn target(&self) -> Option<u16> {
+        match self {
+            Instruction::System(nnn)
+            | Instruction::Jump(nnn)
+            | Instruction::Call(nnn)
+            | Instruction::SetI(nnn)
+            | Instruction::JumpWithOffset(_, nnn) => Some(*nnn),
+            _ => None,
+        }
+    }
+
+    // instruction in Cowgod's syntax (with the usual SUPER-CHIP and XO-CHIP additions)
+    // `address` formats NNN operands, so that callers can replace them with labels
+    pub fn mnemonic(&self, address: impl Fn(u16) -> String) -> String {
+        match *self {
+            Instruction::Clear => "CLS".to_string(),
+            Instruction::Return => "RET".to_string(),
+            Instruction::ScrollDown(n) => format!("SCD {}", n),
+            Instruction::ScrollUp(n) => format!("SCU {}", n),
+            Instruction::ScrollRight => "SCR".to_string(),
+            Instruction::ScrollLeft => "SCL".to_string(),
+            Instruction::Exit => "EXIT".to_string(),
+            Instruction::LowRes => "LOW".to_string(),
+            Instruction::HighRes => "HIGH".to_string(),
+            Instruction::System(nnn) => format!("SYS {}", address(nnn)),
+            Instruction::Jump(nnn) => format!("JP {}", address(nnn)),
+            Instruction::Call(nnn) => format!("CALL {}", address(nnn)),
+            Instruction::SkipIfEqualByte(x, nn) => format!("SE V{:X}, #{:02X}", x, nn),
+            Instruction::SkipIfNotEqualByte(x, nn) => format!("SNE V{:X}, #{:02X}", x, nn),
+            Instruction::SkipIfEqual(x, y) => format!("SE V{:X}, V{:X}", x, y),
+            Instruction::StoreRange(x, y) => format!("SAVE V{:X}, V{:X}", x, y),
+            Instruction::LoadRange(x, y) => format!("LOAD V{:X}, V{:X}", x, y),
+            Instruction::SetByte(x, nn) => format!("LD V{:X}, #{:02X}", x, nn),
+            Instruction::AddByte(x, nn) => format!("ADD V{:X}, #{:02X}", x, nn),
+            Instruction::Set(x, y) => format!("LD V{:X}, V{:X}", x, y),
+            Instruction::Or(x, y) => format!("OR V{:X}, V{:X}", x, y),
+            Instruction::And(x, y) => format!("AND V{:X}, V{:X}", x, y),
+            Instruction::Xor(x, y) => format!("XOR V{:X}, V{:X}", x, y),
+            Instruction::Add(x, y) => format!("ADD V{:X}, V{:X}", x, y),
+            Instruction::Sub(x, y) => format!("SUB V{:X}, V{:X}", x, y),
+            Instruction::ShiftRight(x, y) => format!("SHR V{:X}, V{:X}", x, y),
+            Instruction::SubReverse(x, y) => format!("SUBN V{:X}, V{:X}", x, y),
+            Instruction::ShiftLeft(x, y) => format!("SHL V{:X}, V{:X}", x, y),
+            Instruction::SkipIfNotEqual(x, y) => format!("SNE V{:X}, V{:X}", x, y),
+            Instruction::SetI(nnn) => format!("LD I, {}", address(nnn)),
+            // X is the upper nibble of the address, which one is added depends on the jump quirk
+            Instruction::JumpWithOffset(_, nnn) => format!("JP V0, {}", address(nnn)),
+            Instruction::Random(x, nn) => format!("RND V{:X}, #{:02X}", x, nn),
+            Instruction::Draw(x, y, n) => format!("DRW V{:X}, V{:X}, {}", x, y, n),
+            Instruction::SkipIfKey(x) => format!("SKP V{:X}", x),
+            Instruction::SkipIfNotKey(x) => format!("SKNP V{:X}", x),
+            Instruction::SetILong => "LD I, LONG".to_string(),
+            Instruction::Planes(n) => format!("PLANE {}", n),
+            Instruction::Audio => "AUDIO".to_string(),
+            Instruction::GetDelay(x) => format!("LD V{:X}, DT", x),
+            Instruction::WaitForKey(x) => format!("LD V{:X}, K", x),
+            Instruction::SetDelay(x) => format!("LD DT, V{:X}", x),
+            Instruction::SetSound(x) => format!("LD ST, V{:X}", x),
+            Instruction::AddI(x) => format!("ADD I, V{:X}", x),
+            Instruction::Font(x) => format!("LD F, V{:X}", x),
+            Instruction::BigFont(x) => format!("LD HF, V{:X}", x),
+            Instruction::Bcd(x) => format!("LD B, V{:X}", x),
+            Instruction::Pitch(x) => format!("PITCH V{:X}", x),
+            Instruction::Store(x) => format!("LD [I], V{:X}", x),
+            Instruction::Load(x) => format!("LD V{:X}, [I]", x),
+            Instruction::StoreFlags(x) => format!("LD R, V{:X}", x),
+            Instruction::LoadFlags(x) => format!("LD V{:X}, R", x),
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mnemonic(|nnn| format!("#{:03X}", nnn)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_distinguishes_0nnn_from_schip_instructions() {
+        assert_eq!(Some(Instruction::Clear), Instruction::parse(0x00E0));
+        assert_eq!(Some(Instruction::ScrollDown(4)), Instruction::parse(0x00C4));
+        assert_eq!(Some(Instruction::HighRes), Instruction::parse(0x00FF));
+        assert_eq!(Some(Instruction::System(0x123)), Instruction::parse(0x0123));
+    }
+
+    #[test]
+    fn test_parse_unknown_opcodes() {
+        assert_eq!(None, Instruction::parse(0x5124));
+        assert_eq!(None, Instruction::parse(0x8008));
+        assert_eq!(None, Instruction::parse(0xE19F));
+        assert_eq!(None, Instruction::parse(0xF0FF));
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!("JP #2A0", Instruction::parse(0x12A0).unwrap().to_string());
+        assert_eq!(
+            "LD VA, #0F",
+            Instruction::parse(0x6A0F).unwrap().to_string()
+        );
+        assert_eq!(
+            "DRW V0, V1, 5",
+            Instruction::parse(0xD015).unwrap().to_string()
+        );
+        assert_eq!(
+            "LD [I], V3",
+            Instruction::parse(0xF355).unwrap().to_string()
+        );
+        assert_eq!(
+            "SUBN V1, V2",
+            Instruction::parse(0x8127).unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_mnemonic_with_labels() {
+        let call = Instruction::parse(0x2300).unwrap();
+        assert_eq!(
+            "CALL sub_300",
+            call.mnemonic(|nnn| format!("sub_{:03X}", nnn))
+        );
+    }
+
+    #[test]
+    fn test_encode_is_inverse_of_parse() {
+        for opcode in 0..=u16::MAX {
+            if let Some(instruction) = Instruction::parse(opcode) {
+                assert_eq!(opcode, instruction.encode(), "{}", instruction);
+            }
+        }
+    }
+
+    #[test]
+    fn test_long_instruction_length() {
+        assert_eq!(4, Instruction::parse(0xF000).unwrap().size());
+        assert_eq!(2, Instruction::parse(0xF001).unwrap().size());
+    }
+}
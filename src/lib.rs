@@ -0,0 +1,25 @@
+// CHIP-8 (with SUPER-CHIP and XO-CHIP extensions) emulator core
+// does not depend on any frontend, the raylib one lives in main.rs
+pub mod assembler;
+pub mod beeper;
+pub mod breakpoints;
+pub mod chip8;
+pub mod disassembler;
+pub mod error;
+pub mod instruction;
+pub mod movie;
+pub mod quirks;
+pub mod random;
+pub mod rewind;
+pub mod save_state;
+
+pub use beeper::{AudioSink, Beeper, NullSink, Waveform};
+pub use breakpoints::{Breakpoints, StopReason};
+pub use chip8::{AudioPattern, Chip8, KeypadInput};
+pub use error::Chip8Error;
+pub use instruction::Instruction;
+pub use movie::{Movie, MovieError};
+pub use quirks::Quirks;
+pub use random::{RandomSource, SeededRandom};
+pub use rewind::RewindBuffer;
+pub use save_state::SaveStateError;
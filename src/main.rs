@@ -1,106 +1,652 @@
-use chip8::Chip8;
-use clap::Parser;
-use raylib::consts::KeyboardKey;
+use audio::RaylibSink;
+use chip_8_rs::{
+    assembler, chip8, disassembler, movie, AudioSink, Beeper, Chip8, Chip8Error, KeypadInput,
+    Movie, NullSink, Quirks, RewindBuffer, Waveform,
+};
+use clap::{Parser, Subcommand};
+use config::Config;
+use debugger::Debugger;
+use palette::{Palettes, Rgb};
+use phosphor::{Persistence, Phosphor};
+use raylib::consts::{GamepadButton, KeyboardKey};
 use raylib::prelude::*;
-use std::{fs::File, io::Read};
+use slots::{SaveSlots, NUMBER_OF_SLOTS};
+use std::{fs::File, io::Read, path::Path};
+use viewport::{Fit, Viewport};
 
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// ROM to load
     #[arg(short, required = true)]
-    rom: String,
+    rom: Option<String>,
+
+    /// CPU speed in instructions per second
+    #[arg(
+        long,
+        default_value_t = chip8::DEFAULT_CPU_HZ,
+        value_parser = clap::value_parser!(u32).range(1..=chip8::MAX_CPU_HZ as i64)
+    )]
+    cpu_hz: u32,
+
+    /// CPU speed in instructions per 60 Hz frame, overrides --cpu-hz
+    #[arg(
+        long,
+        conflicts_with = "cpu_hz",
+        value_parser = clap::value_parser!(u32).range(..=chip8::MAX_IPF as i64)
+    )]
+    ipf: Option<u32>,
+
+    /// Beeper frequency in Hz
+    #[arg(long, default_value_t = 440.0)]
+    beep_frequency: f32,
+
+    /// Beeper volume, from 0.0 to 1.0
+    #[arg(long, default_value_t = 0.25)]
+    volume: f32,
+
+    /// Beeper waveform: square, triangle, sawtooth or sine
+    #[arg(long, default_value = "square")]
+    waveform: Waveform,
+
+    /// Disable sound
+    #[arg(long)]
+    mute: bool,
+
+    /// Interpreter behavior to emulate: chip-8, chip-48, super-chip or xo-chip
+    #[arg(long, default_value = "chip-8")]
+    quirks: Quirks,
+
+    /// Stop in the debugger when PC reaches this address (hex), can be repeated
+    #[arg(
+        long = "breakpoint",
+        value_parser = parse_address,
+        conflicts_with_all = ["record", "replay"]
+    )]
+    breakpoints: Vec<usize>,
+
+    /// How many seconds of gameplay can be rewound with BACKSPACE, 0 disables rewinding
+    #[arg(long, default_value_t = 30)]
+    rewind_seconds: u32,
+
+    /// Seed for the random numbers of Cxnn, picked at random by default
+    #[arg(long, conflicts_with = "replay")]
+    seed: Option<u64>,
+
+    /// Config file with the keyboard and gamepad mapping, see README, defaults to
+    /// chip-8-rs/config.toml in the user's config directory
+    #[arg(long)]
+    config: Option<String>,
+
+    /// Palette: classic, amber, green, lcd, octo, hotdog, cga, one from the config file or 2 or 4
+    /// colors like #000000,#FFFFFF, F9 switches palettes while running
+    #[arg(long)]
+    palette: Option<String>,
+
+    /// Initial window size in window pixels per CHIP-8 pixel (1 to 64), the window can be resized
+    #[arg(long, default_value_t = 10, value_parser = clap::value_parser!(u32).range(1..=64))]
+    scale: u32,
+
+    /// How the display is scaled to the window: integer keeps all pixels the same size, aspect
+    /// fills as much of the window as possible
+    #[arg(long, value_enum, default_value_t = Fit::Integer)]
+    fit: Fit,
+
+    /// Keep pixels which were just turned off on the screen to reduce flicker: fade them out or
+    /// merge the last two frames
+    #[arg(long, value_enum, default_value_t = Persistence::Off)]
+    persistence: Persistence,
+
+    /// Frames a pixel takes to fade out with --persistence fade
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..))]
+    fade_frames: u32,
+
+    /// Record the keypad of every frame to a movie file, written when the window is closed
+    #[arg(long)]
+    record: Option<String>,
+
+    /// Play back a movie recorded with --record, the keyboard takes over when it ends
+    #[arg(long, conflicts_with = "record")]
+    replay: Option<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Print annotated disassembly of a ROM
+    Disasm {
+        /// ROM to disassemble
+        #[arg(short, required = true)]
+        rom: String,
+    },
+    /// Assemble a source file into a ROM
+    Assemble {
+        /// Assembly source
+        #[arg(short, required = true)]
+        source: String,
+
+        /// ROM to write
+        #[arg(short, required = true)]
+        output: String,
+    },
 }
 
-const PIXEL_SIZE: usize = 10;
+fn parse_address(value: &str) -> Result<usize, String> {
+    let digits = value.trim_start_matches("0x").trim_start_matches("0X");
+    usize::from_str_radix(digits, 16)
+        .map_err(|error| format!("invalid address `{}`: {}", value, error))
+}
 
-mod chip8;
+// how many frames we are allowed to catch up on after a stall (e.g. window being dragged)
+const MAX_FRAMES_BEHIND: f32 = 5.0;
+// F1..F4 save to slot 1..4, with shift held they load from it
+const SLOT_KEYS: [KeyboardKey; NUMBER_OF_SLOTS] = [
+    KeyboardKey::KEY_F1,
+    KeyboardKey::KEY_F2,
+    KeyboardKey::KEY_F3,
+    KeyboardKey::KEY_F4,
+];
+// switches to the next palette
+const PALETTE_KEY: KeyboardKey = KeyboardKey::KEY_F9;
+// switches between window and fullscreen
+const FULLSCREEN_KEY: KeyboardKey = KeyboardKey::KEY_F11;
+// gamepads raylib can handle at the same time
+const MAX_GAMEPADS: i32 = 4;
+// how long messages (e.g. "saved slot 1") stay on the screen
+const NOTICE_SECONDS: f32 = 2.0;
 
-fn draw(chip: &chip8::Chip8, renderer: &mut RaylibDrawHandle) {
-    for (i, val) in chip.get_vram().iter().enumerate() {
-        let color = if *val { Color::WHITE } else { Color::BLACK };
+mod audio;
+mod config;
+mod debugger;
+mod palette;
+mod phosphor;
+mod slots;
+mod viewport;
 
-        renderer.draw_rectangle(
-            (i % chip8::WIDTH * PIXEL_SIZE) as i32,
-            (i / chip8::WIDTH * PIXEL_SIZE) as i32,
-            PIXEL_SIZE as i32,
-            PIXEL_SIZE as i32,
-            color,
-        );
+// movie being recorded or replayed, rewinding and loading save states are disabled meanwhile
+// because the movie only holds the keypad input of each frame
+enum MovieMode {
+    Off,
+    // movie and the path to write it to
+    Recording(Movie, String),
+    // movie and the next frame to play
+    Replaying(Movie, usize),
+}
+
+impl MovieMode {
+    fn is_on(&self) -> bool {
+        !matches!(self, MovieMode::Off)
+    }
+}
+
+// uploads `colors` of the pixels in VRAM order to the screen texture, which has one texel per
+// pixel of the `width` by `height` display and is made again when the resolution changes
+fn update_screen(
+    screen: &mut Option<Texture2D>,
+    (width, height): (usize, usize),
+    colors: impl Iterator<Item = Rgb>,
+    rl_handle: &mut RaylibHandle,
+    thread: &RaylibThread,
+) {
+    let size = screen
+        .as_ref()
+        .map(|texture| (texture.width() as usize, texture.height() as usize));
+    if size != Some((width, height)) {
+        let image = Image::gen_image_color(width as i32, height as i32, Color::BLACK);
+        let texture = rl_handle
+            .load_texture_from_image(thread, &image)
+            .expect("creating the screen texture failed");
+        *screen = Some(texture);
     }
+
+    let pixels: Vec<u8> = colors
+        .flat_map(|[red, green, blue]| [red, green, blue, 255])
+        .collect();
+    if let Some(texture) = screen {
+        texture.update_texture(&pixels);
+    }
+}
+
+fn draw(screen: &Texture2D, fit: Fit, renderer: &mut RaylibDrawHandle) {
+    let (width, height) = (screen.width(), screen.height());
+    let window = (renderer.get_screen_width(), renderer.get_screen_height());
+    let viewport = Viewport::new(fit, window, (width as usize, height as usize));
+
+    renderer.clear_background(Color::BLACK);
+    renderer.draw_texture_pro(
+        screen,
+        Rectangle::new(0.0, 0.0, width as f32, height as f32),
+        Rectangle::new(viewport.x, viewport.y, viewport.width, viewport.height),
+        Vector2::zero(),
+        0.0,
+        Color::WHITE,
+    );
+}
+
+fn draw_notice(notice: &str, renderer: &mut RaylibDrawHandle) {
+    let height = renderer.get_screen_height();
+    renderer.draw_rectangle(
+        0,
+        height - 20,
+        renderer.get_screen_width(),
+        20,
+        Color::BLACK.fade(0.75),
+    );
+    renderer.draw_text(notice, 10, height - 15, 10, Color::YELLOW);
+}
+
+// shown on top of the last frame when the program faults
+fn draw_error(error: &Chip8Error, renderer: &mut RaylibDrawHandle) {
+    let width = renderer.get_screen_width();
+    let height = renderer.get_screen_height();
+
+    renderer.draw_rectangle(0, 0, width, height, Color::BLACK.fade(0.75));
+    renderer.draw_text("emulation stopped", 10, 10, 20, Color::RED);
+    renderer.draw_text(&error.to_string(), 10, 40, 10, Color::WHITE);
+}
+
+fn read_rom(path: &str) -> Vec<u8> {
+    let mut f = File::open(path).expect("file not found");
+    let mut buffer = Vec::new();
+    f.read_to_end(&mut buffer)
+        .expect("reading rom returned error");
+    buffer
 }
 
 fn main() {
-    let mut f = File::open(Args::parse().rom).expect("file not found");
-
-    let mut chip = chip8::Chip8::new();
-    let mut buffer = [0u8; 4096 - 0x200];
-    let result = f.read(&mut buffer);
-    if result.is_ok() {
-        println!("read {} bytes from rom", result.unwrap());
-        chip.load(&buffer);
-    } else {
-        panic!("reading rom returned error")
+    let args = Args::parse();
+    match &args.command {
+        Some(Command::Disasm { rom }) => {
+            print!("{}", disassembler::disassemble(&read_rom(rom)));
+            return;
+        }
+        Some(Command::Assemble { source, output }) => {
+            match assembler::assemble_file(Path::new(source)) {
+                Ok(rom) => std::fs::write(output, rom).expect("writing rom returned error"),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        None => {}
+    }
+    // clap makes -r required when there is no subcommand
+    let rom_path = args.rom.as_deref().unwrap_or_default();
+    let buffer = read_rom(rom_path);
+    let rom_hash = movie::rom_hash(&buffer);
+    println!(
+        "read {} bytes from rom, hash {:016X}",
+        buffer.len(),
+        rom_hash
+    );
+
+    let config_path = args
+        .config
+        .as_ref()
+        .map(std::path::PathBuf::from)
+        .or_else(Config::default_path);
+    let settings = config_path
+        .map_or(Ok(Config::default()), |path| Config::load(&path))
+        .and_then(|config| {
+            let input_map = InputMap {
+                keys: config.keymap(rom_hash)?.resolve(keyboard_key)?,
+                buttons: config.gamepad_map(rom_hash)?.resolve(gamepad_button)?,
+            };
+            let mut palettes = Palettes::new(config.palettes()?);
+            // --palette overrides the config file
+            if let Some(spec) = args.palette.clone().or(config.palette(rom_hash)?) {
+                palettes.select(&spec)?;
+            }
+            Ok((input_map, palettes))
+        });
+    let (input_map, mut palettes) = match settings {
+        Ok(settings) => settings,
+        Err(error) => {
+            eprintln!("{}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let (mut chip, mut movie) = match &args.replay {
+        // the movie decides quirks, CPU speed and seed
+        Some(path) => {
+            let replay = std::fs::read_to_string(path)
+                .map_err(|error| format!("can't read {}: {}", path, error))
+                .and_then(|text| text.parse::<Movie>().map_err(|error| error.to_string()))
+                .and_then(|movie| {
+                    let chip = movie
+                        .start(&buffer, args.quirks)
+                        .map_err(|error| error.to_string())?;
+                    Ok((chip, movie))
+                });
+            match replay {
+                Ok((chip, movie)) => (chip, MovieMode::Replaying(movie, 0)),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    std::process::exit(1);
+                }
+            }
+        }
+        None => {
+            let mut chip = Chip8::new(args.quirks);
+            chip.set_cpu_hz(match args.ipf {
+                Some(ipf) => ipf * chip8::TIMER_HZ,
+                None => args.cpu_hz,
+            });
+            // printed so a session can be repeated with --seed
+            let seed = args.seed.unwrap_or_else(rand::random);
+            println!("random seed {}", seed);
+            chip.set_seed(seed);
+            if let Err(error) = chip.load(&buffer) {
+                eprintln!("{}", error);
+                std::process::exit(1);
+            }
+            let movie = match &args.record {
+                Some(path) => MovieMode::Recording(Movie::new(&chip, &buffer), path.clone()),
+                None => MovieMode::Off,
+            };
+            (chip, movie)
+        }
+    };
+    for address in &args.breakpoints {
+        chip.breakpoints_mut().add_breakpoint(*address);
     }
 
     let (mut rl_handle, thread) = raylib::init()
         .size(
-            (chip8::WIDTH * PIXEL_SIZE) as i32,
-            (chip8::HEIGHT * PIXEL_SIZE) as i32,
+            (chip8::WIDTH as u32 * args.scale) as i32,
+            (chip8::HEIGHT as u32 * args.scale) as i32,
         )
+        .resizable()
         .title("CHIP-8-rs")
         .build();
+    rl_handle.set_window_min_size(chip8::WIDTH as i32, chip8::HEIGHT as i32);
+    rl_handle.set_target_fps(chip8::TIMER_HZ);
 
-    while !rl_handle.window_should_close() {
-        // input
-        handle_input(&mut rl_handle, &mut chip);
+    // keep running without sound if there is no audio device
+    let audio = RaylibAudio::init_audio_device().ok();
+    let beeper = Beeper::new(args.beep_frequency, args.volume, args.waveform);
+    let mut audio_sink: Box<dyn AudioSink + '_> = match &audio {
+        Some(device) if device.is_audio_device_ready() && !args.mute => {
+            Box::new(RaylibSink::new(device, beeper))
+        }
+        _ => Box::new(NullSink),
+    };
 
-        // tick
-        chip.tick();
+    let frame_period = 1.0 / chip8::TIMER_HZ as f32;
+    let mut frame_accumulator = 0.0;
+    // set when the program faults, emulation stops and the error is shown instead
+    let mut fault: Option<Chip8Error> = None;
+    let mut debugger = Debugger::new();
+    let slots = SaveSlots::new(Path::new(rom_path));
+    // message shown at the bottom of the window, with seconds left to show it
+    let mut notice: Option<(String, f32)> = None;
+    // one snapshot per emulated frame
+    let mut rewind = RewindBuffer::new((args.rewind_seconds * chip8::TIMER_HZ) as usize);
+    let mut phosphor = Phosphor::new(args.persistence, args.fade_frames);
+    // the display, only uploaded again when it changes, None until the first frame
+    let mut screen: Option<Texture2D> = None;
+    // set when the colors change without the display changing, e.g. switching palettes
+    let mut redraw = false;
+    // keys tapped since the last emulated frame, given to the next one
+    let mut taps = 0;
 
-        // draw
-        let mut draw_handle = rl_handle.begin_drawing(&thread);
-        draw(&chip, &mut draw_handle);
-    }
-}
-
-/*
- * 1 2 3 C -> 1 2 3 4
- * 4 5 6 D -> Q W E R
- * 7 8 9 E -> A S D E
- * A 0 B F -> Z X C V
- */
-fn handle_input(rl_handle: &mut RaylibHandle, chip: &mut Chip8) {
-    chip.keypad = [false; 16];
-
-    chip.keypad[0x1] = rl_handle.is_key_down(KeyboardKey::KEY_ONE);
-
-    chip.keypad[0x2] = rl_handle.is_key_down(KeyboardKey::KEY_TWO);
+    while !rl_handle.window_should_close() && !chip.has_exited() {
+        // input
+        let input = read_input(&mut rl_handle, &input_map);
+        taps |= input.pressed;
+        if debugger.is_paused() {
+            // for stepping, no frames run while paused, a movie's frames only get its own input
+            if !movie.is_on() {
+                chip.input(input);
+            }
+            taps = 0;
+        }
+        debugger.set_frames_only(movie.is_on());
+        if fault.is_none() {
+            if let Err(error) = debugger.handle_input(&rl_handle, &mut chip) {
+                eprintln!("{}", error);
+                fault = Some(error);
+            }
+        }
 
-    chip.keypad[0x3] = rl_handle.is_key_down(KeyboardKey::KEY_THREE);
+        // save states
+        for (index, key) in SLOT_KEYS.iter().enumerate() {
+            if !rl_handle.is_key_pressed(*key) {
+                continue;
+            }
 
-    chip.keypad[0xC] = rl_handle.is_key_down(KeyboardKey::KEY_FOUR);
+            let slot = index + 1;
+            let shift = rl_handle.is_key_down(KeyboardKey::KEY_LEFT_SHIFT)
+                || rl_handle.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT);
+            let result = if shift && movie.is_on() {
+                Err("can't load while recording or replaying a movie".to_string())
+            } else if shift {
+                slots.load(slot, &mut chip).map(|_| {
+                    // state from before the fault can be resumed
+                    fault = None;
+                    format!("loaded slot {}", slot)
+                })
+            } else {
+                slots
+                    .save(slot, &chip)
+                    .map(|_| format!("saved slot {}", slot))
+            };
+            let message = result.unwrap_or_else(|error| {
+                eprintln!("{}", error);
+                error
+            });
+            notice = Some((message, NOTICE_SECONDS));
+        }
 
-    chip.keypad[0x4] = rl_handle.is_key_down(KeyboardKey::KEY_Q);
+        if rl_handle.is_key_pressed(FULLSCREEN_KEY) {
+            // borderless keeps the desktop resolution instead of changing the video mode
+            rl_handle.toggle_borderless_windowed();
+        }
+        if rl_handle.is_key_pressed(PALETTE_KEY) {
+            let name = &palettes.next().name;
+            notice = Some((format!("palette {}", name), NOTICE_SECONDS));
+            redraw = true;
+        }
 
-    chip.keypad[0x5] = rl_handle.is_key_down(KeyboardKey::KEY_W);
+        // rewind, one emulated frame back per rendered frame for as long as the key is held
+        let rewinding = rl_handle.is_key_down(KeyboardKey::KEY_BACKSPACE) && !movie.is_on();
+        if rewinding {
+            if let Some(snapshot) = rewind.pop() {
+                chip.load_state(&snapshot)
+                    .expect("snapshots taken by save_state are valid");
+                // snapshots are only taken of frames which did not fault
+                fault = None;
+            }
+        }
 
-    chip.keypad[0x6] = rl_handle.is_key_down(KeyboardKey::KEY_E);
+        // tick, emulated frames are paced by wall clock time and not by the monitor refresh rate
+        frame_accumulator += rl_handle.get_frame_time();
+        frame_accumulator = f32::min(frame_accumulator, MAX_FRAMES_BEHIND * frame_period);
+        if debugger.is_paused() || rewinding {
+            // don't fast forward through the time spent paused
+            frame_accumulator = 0.0;
+        }
+        while frame_accumulator >= frame_period && fault.is_none() {
+            let input = KeypadInput {
+                down: input.down,
+                pressed: std::mem::take(&mut taps),
+            };
+            match &mut movie {
+                MovieMode::Off => chip.input(input),
+                MovieMode::Recording(recording, _) => {
+                    recording.record(input);
+                    chip.input(input);
+                }
+                MovieMode::Replaying(replay, frame) => {
+                    if replay.play(*frame, &mut chip) {
+                        *frame += 1;
+                    } else {
+                        movie = MovieMode::Off;
+                        notice = Some(("replay finished".to_string(), NOTICE_SECONDS));
+                        chip.input(input);
+                    }
+                }
+            }
+            match chip.run_frame() {
+                Ok(Some(reason)) => {
+                    debugger.stop(reason);
+                    frame_accumulator = 0.0;
+                    break;
+                }
+                Ok(None) => rewind.push(chip.save_state()),
+                Err(error) => {
+                    eprintln!("{}", error);
+                    fault = Some(error);
+                }
+            }
+            frame_accumulator -= frame_period;
+        }
 
-    chip.keypad[0xD] = rl_handle.is_key_down(KeyboardKey::KEY_R);
+        // sound
+        let playing =
+            chip.is_sound_playing() && fault.is_none() && !debugger.is_paused() && !rewinding;
+        audio_sink.update(playing, chip.get_audio_pattern());
 
-    chip.keypad[0x7] = rl_handle.is_key_down(KeyboardKey::KEY_A);
+        // draw
+        if let Some((_, seconds_left)) = &mut notice {
+            *seconds_left -= rl_handle.get_frame_time();
+            if *seconds_left <= 0.0 {
+                notice = None;
+            }
+        }
+        let changed = chip.take_display_changed() || !phosphor.is_settled() || screen.is_none();
+        if changed {
+            phosphor.update(chip.get_vram());
+        }
+        if changed || redraw {
+            update_screen(
+                &mut screen,
+                (chip.width(), chip.height()),
+                phosphor.colors(&palettes.current().colors),
+                &mut rl_handle,
+                &thread,
+            );
+            redraw = false;
+        }
+        let mut draw_handle = rl_handle.begin_drawing(&thread);
+        if let Some(screen) = &screen {
+            draw(screen, args.fit, &mut draw_handle);
+        }
+        debugger.draw(&chip, &mut draw_handle);
+        if let Some(error) = &fault {
+            draw_error(error, &mut draw_handle);
+        }
+        if let Some((message, _)) = &notice {
+            draw_notice(message, &mut draw_handle);
+        }
+    }
 
-    chip.keypad[0x8] = rl_handle.is_key_down(KeyboardKey::KEY_S);
+    if let MovieMode::Recording(recording, path) = &movie {
+        match std::fs::write(path, recording.to_string()) {
+            Ok(()) => println!("recorded {} frames to {}", recording.len(), path),
+            Err(error) => eprintln!("can't write {}: {}", path, error),
+        }
+    }
+}
 
-    chip.keypad[0x9] = rl_handle.is_key_down(KeyboardKey::KEY_D);
+// keyboard keys and gamepad buttons bound to each CHIP-8 key
+struct InputMap {
+    keys: [Vec<KeyboardKey>; chip8::NUMBER_OF_KEYS],
+    buttons: [Vec<GamepadButton>; chip8::NUMBER_OF_KEYS],
+}
 
-    chip.keypad[0xE] = rl_handle.is_key_down(KeyboardKey::KEY_F);
+// a CHIP-8 key is down when any of its keys is down or any of its buttons on any gamepad
+// raylib queues key presses, so keys pressed and released within a frame are seen too
+fn read_input(rl_handle: &mut RaylibHandle, input_map: &InputMap) -> KeypadInput {
+    let mut pressed_keys = Vec::new();
+    while let Some(key) = rl_handle.get_key_pressed() {
+        pressed_keys.push(key);
+    }
+    let gamepads: Vec<i32> = (0..MAX_GAMEPADS)
+        .filter(|gamepad| rl_handle.is_gamepad_available(*gamepad))
+        .collect();
 
-    chip.keypad[0xA] = rl_handle.is_key_down(KeyboardKey::KEY_Z);
+    let mut input = KeypadInput::default();
+    for key in 0..chip8::NUMBER_OF_KEYS {
+        let keys = &input_map.keys[key];
+        let buttons = &input_map.buttons[key];
+        let key_down = keys.iter().any(|binding| rl_handle.is_key_down(*binding));
+        let button_down = buttons.iter().any(|button| {
+            gamepads
+                .iter()
+                .any(|gamepad| rl_handle.is_gamepad_button_down(*gamepad, *button))
+        });
+        let key_pressed = keys.iter().any(|binding| pressed_keys.contains(binding));
+        let button_pressed = buttons.iter().any(|button| {
+            gamepads
+                .iter()
+                .any(|gamepad| rl_handle.is_gamepad_button_pressed(*gamepad, *button))
+        });
 
-    chip.keypad[0x0] = rl_handle.is_key_down(KeyboardKey::KEY_X);
+        if key_down || button_down {
+            input.down |= 1 << key;
+        }
+        if key_pressed || button_pressed {
+            input.pressed |= 1 << key;
+        }
+    }
+    input
+}
 
-    chip.keypad[0xB] = rl_handle.is_key_down(KeyboardKey::KEY_C);
+// raylib button for a button name of the config file, the name of the constant without
+// GAMEPAD_BUTTON_
+fn gamepad_button(name: &str) -> Option<GamepadButton> {
+    use GamepadButton::*;
+    const BUTTONS: [GamepadButton; 17] = [
+        GAMEPAD_BUTTON_LEFT_FACE_UP,
+        GAMEPAD_BUTTON_LEFT_FACE_RIGHT,
+        GAMEPAD_BUTTON_LEFT_FACE_DOWN,
+        GAMEPAD_BUTTON_LEFT_FACE_LEFT,
+        GAMEPAD_BUTTON_RIGHT_FACE_UP,
+        GAMEPAD_BUTTON_RIGHT_FACE_RIGHT,
+        GAMEPAD_BUTTON_RIGHT_FACE_DOWN,
+        GAMEPAD_BUTTON_RIGHT_FACE_LEFT,
+        GAMEPAD_BUTTON_LEFT_TRIGGER_1,
+        GAMEPAD_BUTTON_LEFT_TRIGGER_2,
+        GAMEPAD_BUTTON_RIGHT_TRIGGER_1,
+        GAMEPAD_BUTTON_RIGHT_TRIGGER_2,
+        GAMEPAD_BUTTON_MIDDLE_LEFT,
+        GAMEPAD_BUTTON_MIDDLE,
+        GAMEPAD_BUTTON_MIDDLE_RIGHT,
+        GAMEPAD_BUTTON_LEFT_THUMB,
+        GAMEPAD_BUTTON_RIGHT_THUMB,
+    ];
+    BUTTONS
+        .into_iter()
+        .find(|button| format!("{:?}", button).strip_prefix("GAMEPAD_BUTTON_") == Some(name))
+}
 
-    chip.keypad[0xF] = rl_handle.is_key_down(KeyboardKey::KEY_V);
+// raylib key for a key name of the config file, the name of the constant without KEY_
+// all of raylib's keys but KEY_NULL and KEY_MENU, which has the same value as KEY_R
+fn keyboard_key(name: &str) -> Option<KeyboardKey> {
+    use KeyboardKey::*;
+    #[rustfmt::skip]
+    const KEYS: [KeyboardKey; 108] = [
+        KEY_APOSTROPHE, KEY_COMMA, KEY_MINUS, KEY_PERIOD, KEY_SLASH, KEY_ZERO, KEY_ONE, KEY_TWO,
+        KEY_THREE, KEY_FOUR, KEY_FIVE, KEY_SIX, KEY_SEVEN, KEY_EIGHT, KEY_NINE, KEY_SEMICOLON,
+        KEY_EQUAL, KEY_A, KEY_B, KEY_C, KEY_D, KEY_E, KEY_F, KEY_G, KEY_H, KEY_I, KEY_J, KEY_K,
+        KEY_L, KEY_M, KEY_N, KEY_O, KEY_P, KEY_Q, KEY_R, KEY_S, KEY_T, KEY_U, KEY_V, KEY_W, KEY_X,
+        KEY_Y, KEY_Z, KEY_LEFT_BRACKET, KEY_BACKSLASH, KEY_RIGHT_BRACKET, KEY_GRAVE, KEY_SPACE,
+        KEY_ESCAPE, KEY_ENTER, KEY_TAB, KEY_BACKSPACE, KEY_INSERT, KEY_DELETE, KEY_RIGHT, KEY_LEFT,
+        KEY_DOWN, KEY_UP, KEY_PAGE_UP, KEY_PAGE_DOWN, KEY_HOME, KEY_END, KEY_CAPS_LOCK,
+        KEY_SCROLL_LOCK, KEY_NUM_LOCK, KEY_PRINT_SCREEN, KEY_PAUSE, KEY_F1, KEY_F2, KEY_F3, KEY_F4,
+        KEY_F5, KEY_F6, KEY_F7, KEY_F8, KEY_F9, KEY_F10, KEY_F11, KEY_F12, KEY_LEFT_SHIFT,
+        KEY_LEFT_CONTROL, KEY_LEFT_ALT, KEY_LEFT_SUPER, KEY_RIGHT_SHIFT, KEY_RIGHT_CONTROL,
+        KEY_RIGHT_ALT, KEY_RIGHT_SUPER, KEY_KB_MENU, KEY_KP_0, KEY_KP_1, KEY_KP_2, KEY_KP_3,
+        KEY_KP_4, KEY_KP_5, KEY_KP_6, KEY_KP_7, KEY_KP_8, KEY_KP_9, KEY_KP_DECIMAL, KEY_KP_DIVIDE,
+        KEY_KP_MULTIPLY, KEY_KP_SUBTRACT, KEY_KP_ADD, KEY_KP_ENTER, KEY_KP_EQUAL, KEY_BACK,
+        KEY_VOLUME_UP, KEY_VOLUME_DOWN,
+    ];
+    KEYS.into_iter()
+        .find(|key| format!("{:?}", key).strip_prefix("KEY_") == Some(name))
 }
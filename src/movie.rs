@@ -0,0 +1,324 @@
+use crate::{Chip8, KeypadInput, Quirks};
+use std::fmt;
+use std::str::FromStr;
+
+// first line of every movie, with the format version
+const HEADER: &str = "chip-8-rs movie 2";
+
+// keypad input of every emulated frame of a session, along with everything else which decides
+// how the session plays out, so it can be replayed exactly
+// stored as text, for example:
+//
+//   chip-8-rs movie 2
+//   rom 9A3F0C21D4E5B687
+//   seed 000000000000002A
+//   quirks chip-8
+//   cpu-hz 700
+//   0000*120
+//   0020*8
+//   0000/0100
+//
+// a frame is the keys down at its end as a hex bitmask (bit N is key N), `/TAPS` adds the keys
+// pressed and released during it, `*COUNT` repeats it
+#[derive(Clone, Debug, PartialEq)]
+pub struct Movie {
+    pub rom_hash: u64,
+    // None if a custom random source was used, Cxnn then gives different numbers when replaying
+    pub seed: Option<u64>,
+    // None if the quirks are not one of the presets, replaying then needs the same quirks
+    pub quirks: Option<Quirks>,
+    pub cpu_hz: u32,
+    frames: Vec<KeypadInput>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MovieError {
+    // text is not a valid movie
+    Parse { line: usize, message: String },
+    // movie was recorded with a different ROM
+    RomMismatch { expected: u64, actual: u64 },
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::Parse { line, message } => write!(f, "movie line {}: {}", line, message),
+            MovieError::RomMismatch { expected, actual } => write!(
+                f,
+                "movie was recorded with a different ROM: hash {:016X}, this ROM is {:016X}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+// 64-bit FNV-1a, identifies the ROM a movie was recorded with
+pub fn rom_hash(rom: &[u8]) -> u64 {
+    rom.iter().fold(0xCBF29CE484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001B3)
+    })
+}
+
+impl Movie {
+    // starts recording a session of `chip` running `rom`, from its current settings
+    pub fn new(chip: &Chip8, rom: &[u8]) -> Self {
+        Movie {
+            rom_hash: rom_hash(rom),
+            seed: chip.get_seed(),
+            quirks: Some(chip.get_quirks()).filter(|quirks| quirks.preset_name().is_some()),
+            cpu_hz: chip.get_cpu_hz(),
+            frames: Vec::new(),
+        }
+    }
+
+    // number of recorded frames
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    // call with the input given to Chip8::input before running each frame
+    pub fn record(&mut self, input: KeypadInput) {
+        // presses of keys still down don't change anything
+        self.frames.push(KeypadInput {
+            down: input.down,
+            pressed: input.pressed & !input.down,
+        });
+    }
+
+    // machine for replaying the movie, the ROM is loaded but not run yet
+    // `quirks` are used if the movie does not name a preset
+    pub fn start(&self, rom: &[u8], quirks: Quirks) -> Result<Chip8, MovieError> {
+        let actual = rom_hash(rom);
+        if actual != self.rom_hash {
+            return Err(MovieError::RomMismatch {
+                expected: self.rom_hash,
+                actual,
+            });
+        }
+
+        let mut chip = Chip8::new(self.quirks.unwrap_or(quirks));
+        if let Some(seed) = self.seed {
+            chip.set_seed(seed);
+        }
+        chip.set_cpu_hz(self.cpu_hz);
+        // hash matched, so the ROM was loaded successfully when recording
+        chip.load(rom).expect("recorded ROM fits in memory");
+        Ok(chip)
+    }
+
+    // gives `chip` the input of `frame`, call before running the frame
+    // returns false once the movie has ended
+    pub fn play(&self, frame: usize, chip: &mut Chip8) -> bool {
+        let Some(input) = self.frames.get(frame) else {
+            return false;
+        };
+        chip.input(*input);
+        true
+    }
+}
+
+impl fmt::Display for Movie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "rom {:016X}", self.rom_hash)?;
+        if let Some(seed) = self.seed {
+            writeln!(f, "seed {:016X}", seed)?;
+        }
+        if let Some(name) = self.quirks.and_then(|quirks| quirks.preset_name()) {
+            writeln!(f, "quirks {}", name)?;
+        }
+        writeln!(f, "cpu-hz {}", self.cpu_hz)?;
+
+        let mut frames = self.frames.iter().peekable();
+        while let Some(input) = frames.next() {
+            let mut count = 1;
+            while frames.next_if_eq(&input).is_some() {
+                count += 1;
+            }
+            write!(f, "{:04X}", input.down)?;
+            if input.pressed != 0 {
+                write!(f, "/{:04X}", input.pressed)?;
+            }
+            if count > 1 {
+                write!(f, "*{}", count)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Movie {
+    type Err = MovieError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut lines = text
+            .lines()
+            .enumerate()
+            .map(|(index, line)| (index + 1, line));
+        let error = |line, message: String| MovieError::Parse { line, message };
+
+        match lines.next() {
+            Some((_, HEADER)) => {}
+            _ => return Err(error(1, format!("expected `{}`", HEADER))),
+        }
+
+        let mut rom_hash = None;
+        let mut seed = None;
+        let mut quirks = None;
+        let mut cpu_hz = None;
+        let mut frames = Vec::new();
+        for (line_number, line) in lines {
+            let invalid = || error(line_number, format!("invalid line `{}`", line));
+            match line.split_once(' ') {
+                Some(("rom", value)) => {
+                    rom_hash = Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?);
+                }
+                Some(("seed", value)) => {
+                    seed = Some(u64::from_str_radix(value, 16).map_err(|_| invalid())?);
+                }
+                Some(("quirks", value)) => {
+                    quirks = Some(
+                        value
+                            .parse()
+                            .map_err(|message| error(line_number, message))?,
+                    );
+                }
+                Some(("cpu-hz", value)) => {
+                    cpu_hz = Some(value.parse().map_err(|_| invalid())?);
+                }
+                Some(_) => return Err(invalid()),
+                None => {
+                    let (input, count) = line.split_once('*').unwrap_or((line, "1"));
+                    let (down, pressed) = input.split_once('/').unwrap_or((input, "0"));
+                    let input = KeypadInput {
+                        down: u16::from_str_radix(down, 16).map_err(|_| invalid())?,
+                        pressed: u16::from_str_radix(pressed, 16).map_err(|_| invalid())?,
+                    };
+                    let count: usize = count.parse().map_err(|_| invalid())?;
+                    frames.extend(std::iter::repeat_n(input, count));
+                }
+            }
+        }
+
+        let last_line = text.lines().count();
+        let missing = |field| error(last_line, format!("missing `{}`", field));
+        Ok(Movie {
+            rom_hash: rom_hash.ok_or_else(|| missing("rom"))?,
+            seed,
+            quirks,
+            cpu_hz: cpu_hz.ok_or_else(|| missing("cpu-hz"))?,
+            frames,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::assembler::assemble;
+
+    #[test]
+    fn test_rom_hash() {
+        assert_eq!(0xCBF29CE484222325, rom_hash(&[]));
+        assert_eq!(0xAF63DC4C8601EC8C, rom_hash(b"a"));
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        let mut chip = Chip8::new(Quirks::SUPER_CHIP);
+        chip.set_seed(42);
+        let mut movie = Movie::new(&chip, &[0x12, 0x00]);
+        for frame in 0..10 {
+            let down = if (3..6).contains(&frame) { 0x20 } else { 0 };
+            let pressed = if frame == 8 { 0x120 } else { down };
+            movie.record(KeypadInput { down, pressed });
+        }
+
+        let text = movie.to_string();
+        assert!(text.contains("\nseed 000000000000002A\nquirks super-chip\n"));
+        assert!(text.ends_with("\n0000*3\n0020*3\n0000*2\n0000/0120\n0000\n"));
+        assert_eq!(Ok(movie), text.parse());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            Err(MovieError::Parse {
+                line: 1,
+                message: "expected `chip-8-rs movie 2`".to_string()
+            }),
+            "chip-8-rs movie 1\n".parse::<Movie>()
+        );
+        assert_eq!(
+            Err(MovieError::Parse {
+                line: 3,
+                message: "invalid line `00x0`".to_string()
+            }),
+            "chip-8-rs movie 2\nrom 0\n00x0\n".parse::<Movie>()
+        );
+        assert!(matches!(
+            "chip-8-rs movie 2\nrom 0\nseed 0\n".parse::<Movie>(),
+            Err(MovieError::Parse { line: 3, .. })
+        ));
+    }
+
+    #[test]
+    fn test_replay_is_exact() {
+        // V1 counts frames key 5 was down, V2 is random
+        let rom = assemble(
+            "loop:
+                LD V0, 5
+                SKNP V0
+                ADD V1, 1
+                RND V2, #FF
+                LD V3, 1
+                LD DT, V3
+            wait:
+                LD V3, DT
+                SE V3, 0
+                JP wait
+                JP loop",
+        )
+        .unwrap();
+
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&rom).unwrap();
+        let mut movie = Movie::new(&chip, &rom);
+        for frame in 0..30 {
+            // held every third frame, tapped in between every other time
+            let input = KeypadInput {
+                down: if frame % 3 == 0 { 1 << 5 } else { 0 },
+                pressed: if frame % 2 == 0 { 1 << 5 } else { 0 },
+            };
+            chip.input(input);
+            movie.record(input);
+            chip.run_frame().unwrap();
+        }
+
+        let movie: Movie = movie.to_string().parse().unwrap();
+        let mut replay = movie.start(&rom, Quirks::default()).unwrap();
+        let mut frame = 0;
+        while movie.play(frame, &mut replay) {
+            replay.run_frame().unwrap();
+            frame += 1;
+        }
+        assert_eq!(30, frame);
+        assert_ne!(0, chip.get_registers()[1]);
+        assert_eq!(chip.save_state(), replay.save_state());
+
+        assert_eq!(
+            Err(MovieError::RomMismatch {
+                expected: rom_hash(&rom),
+                actual: rom_hash(&[0x00, 0xE0])
+            }),
+            movie.start(&[0x00, 0xE0], Quirks::default()).map(|_| ())
+        );
+    }
+}
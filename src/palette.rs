@@ -0,0 +1,197 @@
+use std::str::FromStr;
+
+pub type Rgb = [u8; 3];
+
+// colors for each pixel value, bit 0 is the first plane and bit 1 the second one, so only XO-CHIP
+// programs drawing to the second plane use the last two
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub name: String,
+    pub colors: [Rgb; 4],
+}
+
+const PRESETS: [(&str, [Rgb; 4]); 7] = [
+    // white on black, raylib's GRAY and DARKGRAY for the second plane
+    (
+        "classic",
+        [[0, 0, 0], [255, 255, 255], [130, 130, 130], [80, 80, 80]],
+    ),
+    (
+        "amber",
+        [[26, 14, 0], [255, 176, 0], [153, 92, 0], [255, 214, 128]],
+    ),
+    (
+        "green",
+        [[0, 20, 0], [51, 255, 51], [0, 140, 20], [170, 255, 170]],
+    ),
+    // Game Boy LCD
+    (
+        "lcd",
+        [[155, 188, 15], [15, 56, 15], [139, 172, 15], [48, 98, 48]],
+    ),
+    // Octo's themes
+    (
+        "octo",
+        [[153, 102, 0], [255, 204, 0], [255, 102, 0], [102, 34, 0]],
+    ),
+    (
+        "hotdog",
+        [[0, 0, 0], [255, 0, 0], [255, 255, 0], [255, 255, 255]],
+    ),
+    (
+        "cga",
+        [[0, 0, 0], [255, 0, 255], [0, 255, 255], [255, 255, 255]],
+    ),
+];
+
+pub fn parse_color(text: &str) -> Result<Rgb, String> {
+    let digits = text.strip_prefix('#').unwrap_or(text);
+    let value = u32::from_str_radix(digits, 16)
+        .ok()
+        .filter(|_| digits.len() == 6)
+        .ok_or_else(|| format!("invalid color `{}`, expected #RRGGBB", text))?;
+    let [_, red, green, blue] = value.to_be_bytes();
+    Ok([red, green, blue])
+}
+
+// `amount` of the way from `from` to `to`
+pub fn blend(from: Rgb, to: Rgb, amount: f32) -> Rgb {
+    let mix = |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * amount).round() as u8;
+    [
+        mix(from[0], to[0]),
+        mix(from[1], to[1]),
+        mix(from[2], to[2]),
+    ]
+}
+
+// a preset name or 2 or 4 comma separated colors, e.g. `#000000,#FFFFFF`
+// with 2 colors the second plane is drawn in shades between them
+impl FromStr for Palette {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        if let Some((name, colors)) = PRESETS.iter().find(|(name, _)| *name == text) {
+            return Ok(Palette {
+                name: name.to_string(),
+                colors: *colors,
+            });
+        }
+        if !text.contains(',') && !text.starts_with('#') {
+            let names: Vec<&str> = PRESETS.iter().map(|(name, _)| *name).collect();
+            return Err(format!(
+                "unknown palette `{}`, expected one of: {} or 2 or 4 colors like #000000,#FFFFFF",
+                text,
+                names.join(", ")
+            ));
+        }
+
+        let colors = text
+            .split(',')
+            .map(|color| parse_color(color.trim()))
+            .collect::<Result<Vec<Rgb>, String>>()?;
+        let colors = match colors[..] {
+            [background, foreground] => [
+                background,
+                foreground,
+                blend(background, foreground, 0.5),
+                blend(background, foreground, 0.3),
+            ],
+            [a, b, c, d] => [a, b, c, d],
+            _ => return Err(format!("palette `{}` needs 2 or 4 colors", text)),
+        };
+        Ok(Palette {
+            name: "custom".to_string(),
+            colors,
+        })
+    }
+}
+
+// palettes F9 cycles through, the presets followed by the ones from the config file
+pub struct Palettes {
+    list: Vec<Palette>,
+    current: usize,
+}
+
+impl Palettes {
+    pub fn new(custom: Vec<Palette>) -> Self {
+        let mut list: Vec<Palette> = PRESETS
+            .iter()
+            .map(|(name, colors)| Palette {
+                name: name.to_string(),
+                colors: *colors,
+            })
+            .collect();
+        list.extend(custom);
+        Palettes { list, current: 0 }
+    }
+
+    pub fn current(&self) -> &Palette {
+        &self.list[self.current]
+    }
+
+    // switches to the palette with this name, or to a new one made of the colors
+    pub fn select(&mut self, spec: &str) -> Result<(), String> {
+        if let Some(index) = self.list.iter().position(|palette| palette.name == spec) {
+            self.current = index;
+            return Ok(());
+        }
+        self.list.push(spec.parse()?);
+        self.current = self.list.len() - 1;
+        Ok(())
+    }
+
+    pub fn next(&mut self) -> &Palette {
+        self.current = (self.current + 1) % self.list.len();
+        self.current()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_color() {
+        assert_eq!(Ok([0x12, 0xAB, 0xFF]), parse_color("#12abFF"));
+        assert_eq!(Ok([0, 0, 0]), parse_color("000000"));
+        assert!(parse_color("#FFF").is_err());
+        assert!(parse_color("#GGGGGG").is_err());
+    }
+
+    #[test]
+    fn test_parse_palette() {
+        assert_eq!(Ok(PRESETS[1].1), "amber".parse().map(|p: Palette| p.colors));
+        assert_eq!(
+            Ok([[0, 0, 0], [200, 100, 0], [100, 50, 0], [60, 30, 0]]),
+            "#000000, #C86400".parse().map(|p: Palette| p.colors)
+        );
+        assert_eq!(
+            Ok([[1, 1, 1], [2, 2, 2], [3, 3, 3], [4, 4, 4]]),
+            "#010101,#020202,#030303,#040404"
+                .parse()
+                .map(|p: Palette| p.colors)
+        );
+        assert!("#000000,#FFFFFF,#FFFFFF".parse::<Palette>().is_err());
+        assert!("sepia".parse::<Palette>().is_err());
+    }
+
+    #[test]
+    fn test_cycle_palettes() {
+        let custom = Palette {
+            name: "mine".to_string(),
+            colors: [[1, 2, 3]; 4],
+        };
+        let mut palettes = Palettes::new(vec![custom]);
+        assert_eq!("classic", palettes.current().name);
+        assert_eq!("amber", palettes.next().name);
+
+        palettes.select("mine").unwrap();
+        assert_eq!("mine", palettes.current().name);
+        assert_eq!("classic", palettes.next().name);
+
+        palettes.select("#000000,#FFFFFF").unwrap();
+        assert_eq!("custom", palettes.current().name);
+        assert!(palettes.select("nope").is_err());
+        assert_eq!("custom", palettes.current().name);
+    }
+}
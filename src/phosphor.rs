@@ -0,0 +1,142 @@
+use crate::palette::{self, Rgb};
+use clap::ValueEnum;
+
+// how pixels that were just turned off are shown, CHIP-8 games erase and redraw their sprites
+// with XOR so moving sprites flicker when only the current frame is shown
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Persistence {
+    // only the current frame
+    Off,
+    // pixels fade out over a number of frames, like the phosphor of a CRT
+    Fade,
+    // pixels set in the current or the previous frame are shown
+    Merge,
+}
+
+// what was on the screen in the last frames, updated when the display changed until it settles
+pub struct Phosphor {
+    persistence: Persistence,
+    // brightness lost per frame by a pixel which was turned off
+    decay: f32,
+    // last value each pixel was set to and how bright it still is, from 0.0 to 1.0
+    pixels: Vec<(u8, f32)>,
+    previous: Vec<u8>,
+    // false while pixels are still fading or merged with the previous frame
+    settled: bool,
+}
+
+impl Phosphor {
+    // `fade_frames` is how long a pixel takes to fade out, larger is smoother but blurrier
+    pub fn new(persistence: Persistence, fade_frames: u32) -> Self {
+        Phosphor {
+            persistence,
+            decay: 1.0 / fade_frames.max(1) as f32,
+            pixels: Vec::new(),
+            previous: Vec::new(),
+            settled: true,
+        }
+    }
+
+    pub fn update(&mut self, vram: &[u8]) {
+        // the resolution changed, what was shown before doesn't line up anymore
+        if self.pixels.len() != vram.len() {
+            self.pixels = vec![(0, 0.0); vram.len()];
+            self.previous = vram.to_vec();
+        }
+
+        self.settled = true;
+        for ((pixel, previous), value) in self.pixels.iter_mut().zip(&mut self.previous).zip(vram) {
+            *pixel = match self.persistence {
+                Persistence::Off => (*value, 1.0),
+                Persistence::Fade if *value != 0 => (*value, 1.0),
+                Persistence::Fade => (pixel.0, (pixel.1 - self.decay).max(0.0)),
+                Persistence::Merge => (*value | *previous, 1.0),
+            };
+            self.settled &= match self.persistence {
+                Persistence::Off => true,
+                Persistence::Fade => *value != 0 || pixel.1 == 0.0,
+                Persistence::Merge => *value == *previous,
+            };
+            *previous = *value;
+        }
+    }
+
+    // true if updating with the same VRAM again wouldn't change the colors
+    pub fn is_settled(&self) -> bool {
+        self.settled
+    }
+
+    // color of every pixel with `colors` of the palette, in VRAM order
+    pub fn colors<'a>(&'a self, colors: &'a [Rgb; 4]) -> impl Iterator<Item = Rgb> + 'a {
+        self.pixels.iter().map(|(value, brightness)| {
+            palette::blend(colors[0], colors[*value as usize], *brightness)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [Rgb; 4] = [[0, 0, 0], [200, 200, 200], [100, 0, 0], [0, 100, 0]];
+
+    fn shown(phosphor: &Phosphor) -> Vec<Rgb> {
+        phosphor.colors(&COLORS).collect()
+    }
+
+    #[test]
+    fn test_off_shows_current_frame() {
+        let mut phosphor = Phosphor::new(Persistence::Off, 4);
+        phosphor.update(&[1, 2]);
+        phosphor.update(&[0, 3]);
+        assert_eq!(vec![COLORS[0], COLORS[3]], shown(&phosphor));
+    }
+
+    #[test]
+    fn test_fade_pixels_out() {
+        let mut phosphor = Phosphor::new(Persistence::Fade, 4);
+        phosphor.update(&[1, 2]);
+        assert_eq!(vec![COLORS[1], COLORS[2]], shown(&phosphor));
+
+        phosphor.update(&[0, 2]);
+        assert_eq!(vec![[150, 150, 150], COLORS[2]], shown(&phosphor));
+        phosphor.update(&[0, 2]);
+        assert_eq!(vec![[100, 100, 100], COLORS[2]], shown(&phosphor));
+
+        // turned on again at full brightness
+        phosphor.update(&[1, 2]);
+        assert_eq!(vec![COLORS[1], COLORS[2]], shown(&phosphor));
+
+        for _ in 0..3 {
+            phosphor.update(&[0, 0]);
+            assert!(!phosphor.is_settled());
+        }
+        phosphor.update(&[0, 0]);
+        assert!(phosphor.is_settled());
+        assert_eq!(vec![COLORS[0], COLORS[0]], shown(&phosphor));
+    }
+
+    #[test]
+    fn test_merge_last_two_frames() {
+        let mut phosphor = Phosphor::new(Persistence::Merge, 4);
+        phosphor.update(&[1, 0, 2]);
+        phosphor.update(&[0, 2, 1]);
+        assert_eq!(vec![COLORS[1], COLORS[2], COLORS[3]], shown(&phosphor));
+        phosphor.update(&[0, 0, 0]);
+        assert_eq!(vec![COLORS[0], COLORS[2], COLORS[1]], shown(&phosphor));
+        assert!(!phosphor.is_settled());
+        phosphor.update(&[0, 0, 0]);
+        assert!(phosphor.is_settled());
+    }
+
+    #[test]
+    fn test_resolution_change_resets() {
+        let mut phosphor = Phosphor::new(Persistence::Fade, 4);
+        phosphor.update(&[1, 1]);
+        phosphor.update(&[0, 0, 0, 1]);
+        assert_eq!(
+            vec![COLORS[0], COLORS[0], COLORS[0], COLORS[1]],
+            shown(&phosphor)
+        );
+    }
+}
@@ -0,0 +1,100 @@
+use std::str::FromStr;
+
+// how Fx55/Fx65 leave I after storing/loading registers
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MemoryIncrement {
+    // I = I + X + 1 (COSMAC VIP)
+    XPlusOne,
+    // I = I + X (CHIP-48)
+    X,
+    // I is not modified (SUPER-CHIP 1.1)
+    Unchanged,
+}
+
+// behavioral differences between CHIP-8 interpreters
+// see https://github.com/Timendus/chip8-test-suite#quirks-test
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Quirks {
+    // 8xy6/8xyE shift VX in place instead of storing shifted VY in VX
+    pub shift: bool,
+    // 8xy1/8xy2/8xy3 reset VF to 0
+    pub vf_reset: bool,
+    pub memory_increment: MemoryIncrement,
+    // Bnnn jumps to xnn + VX instead of nnn + V0
+    pub jump_with_vx: bool,
+    // Dxyn clips sprites at the edges of the screen instead of wrapping them around
+    pub clipping: bool,
+    // Dxyn waits for the vertical blank, at most one sprite is drawn per frame
+    pub display_wait: bool,
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift: false,
+        vf_reset: true,
+        memory_increment: MemoryIncrement::XPlusOne,
+        jump_with_vx: false,
+        clipping: true,
+        display_wait: true,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        shift: true,
+        vf_reset: false,
+        memory_increment: MemoryIncrement::X,
+        jump_with_vx: true,
+        clipping: true,
+        display_wait: false,
+    };
+
+    pub const SUPER_CHIP: Quirks = Quirks {
+        shift: true,
+        vf_reset: false,
+        memory_increment: MemoryIncrement::Unchanged,
+        jump_with_vx: true,
+        clipping: true,
+        display_wait: false,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        shift: false,
+        vf_reset: false,
+        memory_increment: MemoryIncrement::XPlusOne,
+        jump_with_vx: false,
+        clipping: false,
+        display_wait: false,
+    };
+
+    pub const PRESET_NAMES: [&'static str; 4] = ["chip-8", "chip-48", "super-chip", "xo-chip"];
+
+    // name of the preset these quirks are equal to, as accepted by from_str
+    pub fn preset_name(&self) -> Option<&'static str> {
+        Quirks::PRESET_NAMES
+            .into_iter()
+            .find(|name| name.parse::<Quirks>().as_ref() == Ok(self))
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Quirks::COSMAC_VIP
+    }
+}
+
+impl FromStr for Quirks {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name {
+            "chip-8" => Ok(Quirks::COSMAC_VIP),
+            "chip-48" => Ok(Quirks::CHIP_48),
+            "super-chip" => Ok(Quirks::SUPER_CHIP),
+            "xo-chip" => Ok(Quirks::XO_CHIP),
+            _ => Err(format!(
+                "unknown quirks preset `{}`, expected one of: {}",
+                name,
+                Quirks::PRESET_NAMES.join(", ")
+            )),
+        }
+    }
+}
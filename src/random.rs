@@ -0,0 +1,71 @@
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+// where Cxnn gets its random numbers from, see Chip8::set_random_source
+pub trait RandomSource {
+    fn next_byte(&mut self) -> u8;
+
+    // how far into its sequence the source is, saved in save states so Cxnn gives the same
+    // numbers after loading one, sources which can't be restored return None
+    fn position(&self) -> Option<u128> {
+        None
+    }
+
+    fn set_position(&mut self, _position: u128) {}
+}
+
+// default source, the same seed gives the same numbers on every platform
+pub struct SeededRandom {
+    rng: ChaCha8Rng,
+}
+
+impl SeededRandom {
+    pub fn new(seed: u64) -> Self {
+        SeededRandom {
+            rng: ChaCha8Rng::seed_from_u64(seed),
+        }
+    }
+}
+
+impl RandomSource for SeededRandom {
+    fn next_byte(&mut self) -> u8 {
+        self.rng.gen()
+    }
+
+    fn position(&self) -> Option<u128> {
+        Some(self.rng.get_word_pos())
+    }
+
+    fn set_position(&mut self, position: u128) {
+        self.rng.set_word_pos(position);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_gives_same_numbers() {
+        let numbers = |seed| {
+            let mut random = SeededRandom::new(seed);
+            (0..16).map(|_| random.next_byte()).collect::<Vec<u8>>()
+        };
+        assert_eq!(numbers(1), numbers(1));
+        assert_ne!(numbers(1), numbers(2));
+    }
+
+    #[test]
+    fn test_set_position_repeats_numbers() {
+        let mut random = SeededRandom::new(1);
+        random.next_byte();
+        let position = random.position().unwrap();
+        let numbers: Vec<u8> = (0..4).map(|_| random.next_byte()).collect();
+
+        random.set_position(position);
+        assert_eq!(
+            numbers,
+            (0..4).map(|_| random.next_byte()).collect::<Vec<u8>>()
+        );
+    }
+}
@@ -0,0 +1,213 @@
+use std::collections::VecDeque;
+
+// bounded history of Chip8::save_state snapshots, newest last
+// only the newest snapshot is kept whole, every older one is stored as a delta against
+// the snapshot after it: XOR of the two with runs of unchanged bytes reduced to their length
+// a frame usually touches a handful of bytes of RAM and VRAM, so a delta is tens of bytes
+// instead of the ~70 KiB of a full snapshot
+pub struct RewindBuffer {
+    capacity: usize,
+    newest: Option<Vec<u8>>,
+    // deltas[i] turns snapshot i + 1 back into snapshot i
+    deltas: VecDeque<Vec<u8>>,
+}
+
+impl RewindBuffer {
+    // keeps at most `capacity` snapshots, oldest ones are dropped first
+    pub fn new(capacity: usize) -> Self {
+        RewindBuffer {
+            capacity,
+            newest: None,
+            deltas: VecDeque::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.deltas.len() + self.newest.is_some() as usize
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newest.is_none()
+    }
+
+    pub fn clear(&mut self) {
+        self.newest = None;
+        self.deltas.clear();
+    }
+
+    // bytes used by the stored snapshots
+    pub fn size(&self) -> usize {
+        self.newest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+    }
+
+    pub fn push(&mut self, snapshot: Vec<u8>) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        if let Some(previous) = self.newest.take() {
+            self.deltas.push_back(encode_delta(&snapshot, &previous));
+            if self.deltas.len() >= self.capacity {
+                self.deltas.pop_front();
+            }
+        }
+        self.newest = Some(snapshot);
+    }
+
+    // removes and returns the newest snapshot, the one before it becomes the newest
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let newest = self.newest.take()?;
+        self.newest = self
+            .deltas
+            .pop_back()
+            .map(|delta| apply_delta(&delta, &newest));
+        Some(newest)
+    }
+}
+
+fn write_varint(output: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        output.push((value as u8 & 0x7F) | 0x80);
+        value >>= 7;
+    }
+    output.push(value as u8);
+}
+
+fn read_varint(input: &[u8], position: &mut usize) -> usize {
+    let mut value = 0;
+    let mut shift = 0;
+    loop {
+        let byte = input[*position];
+        *position += 1;
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return value;
+        }
+        shift += 7;
+    }
+}
+
+// delta which turns `from` into `to`:
+// length of `to`, then pairs of (unchanged bytes, changed bytes) counts, each followed by
+// the XOR of the changed bytes, trailing unchanged bytes are left out
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    write_varint(&mut delta, to.len());
+
+    let xor = |index: usize| from.get(index).copied().unwrap_or(0) ^ to[index];
+    let mut index = 0;
+    while index < to.len() {
+        let unchanged_start = index;
+        while index < to.len() && xor(index) == 0 {
+            index += 1;
+        }
+        if index == to.len() {
+            break;
+        }
+        let changed_start = index;
+        while index < to.len() && xor(index) != 0 {
+            index += 1;
+        }
+
+        write_varint(&mut delta, changed_start - unchanged_start);
+        write_varint(&mut delta, index - changed_start);
+        delta.extend((changed_start..index).map(xor));
+    }
+
+    delta
+}
+
+fn apply_delta(delta: &[u8], from: &[u8]) -> Vec<u8> {
+    let mut position = 0;
+    let length = read_varint(delta, &mut position);
+
+    let mut to = from[..from.len().min(length)].to_vec();
+    to.resize(length, 0);
+
+    let mut index = 0;
+    while position < delta.len() {
+        index += read_varint(delta, &mut position);
+        let changed = read_varint(delta, &mut position);
+        for byte in &delta[position..position + changed] {
+            to[index] ^= byte;
+            index += 1;
+        }
+        position += changed;
+    }
+
+    to
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Chip8, Quirks};
+
+    #[test]
+    fn test_delta_round_trip() {
+        let from = vec![1, 2, 3, 4, 5, 6, 7, 8];
+        let to = vec![1, 2, 0, 4, 5, 6, 9, 9, 10];
+
+        let delta = encode_delta(&from, &to);
+        assert_eq!(to, apply_delta(&delta, &from));
+
+        let delta = encode_delta(&to, &from);
+        assert_eq!(from, apply_delta(&delta, &to));
+    }
+
+    #[test]
+    fn test_delta_of_small_change_is_small() {
+        let from = vec![0; 0x10000];
+        let mut to = from.clone();
+        to[0x200] = 0xFF;
+        to[0x8000] = 0x01;
+
+        assert!(encode_delta(&from, &to).len() < 16);
+    }
+
+    #[test]
+    fn test_pop_returns_snapshots_newest_first() {
+        let mut buffer = RewindBuffer::new(10);
+        buffer.push(vec![1, 1, 1]);
+        buffer.push(vec![1, 2, 1]);
+        buffer.push(vec![1, 2, 3, 4]);
+        assert_eq!(3, buffer.len());
+
+        assert_eq!(Some(vec![1, 2, 3, 4]), buffer.pop());
+        assert_eq!(Some(vec![1, 2, 1]), buffer.pop());
+        assert_eq!(Some(vec![1, 1, 1]), buffer.pop());
+        assert_eq!(None, buffer.pop());
+        assert!(buffer.is_empty());
+    }
+
+    #[test]
+    fn test_oldest_snapshots_are_dropped() {
+        let mut buffer = RewindBuffer::new(3);
+        for value in 0..5 {
+            buffer.push(vec![value; 4]);
+        }
+        assert_eq!(3, buffer.len());
+
+        assert_eq!(Some(vec![4; 4]), buffer.pop());
+        assert_eq!(Some(vec![3; 4]), buffer.pop());
+        assert_eq!(Some(vec![2; 4]), buffer.pop());
+        assert_eq!(None, buffer.pop());
+    }
+
+    #[test]
+    fn test_rewind_machine_state() {
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&[0x70, 0x01].repeat(10)).unwrap(); // V0 += 1, 10 times
+        let mut buffer = RewindBuffer::new(100);
+        for _ in 0..10 {
+            chip.tick().unwrap();
+            buffer.push(chip.save_state());
+        }
+        // only V0 and PC change between the snapshots
+        assert!(buffer.size() < chip.save_state().len() + 9 * 32);
+
+        buffer.pop();
+        chip.load_state(&buffer.pop().unwrap()).unwrap();
+        assert_eq!(9, chip.get_registers()[0]);
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+
+// every save state starts with the magic followed by the format version
+const MAGIC: &[u8; 4] = b"C8SS";
+// bump whenever the layout written by Chip8::save_state changes
+pub const VERSION: u8 = 3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SaveStateError {
+    // data is not a save state at all
+    BadMagic,
+    // save state was written by a different version of the emulator
+    UnsupportedVersion { version: u8 },
+    // data ends before the whole state was read
+    Truncated,
+    // data is longer than the state
+    TrailingData,
+    // a field has a value the machine can't be in
+    InvalidValue { field: &'static str },
+}
+
+impl fmt::Display for SaveStateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveStateError::BadMagic => write!(f, "not a save state"),
+            SaveStateError::UnsupportedVersion { version } => write!(
+                f,
+                "unsupported save state version {}, expected {}",
+                version, VERSION
+            ),
+            SaveStateError::Truncated => write!(f, "save state is truncated"),
+            SaveStateError::TrailingData => write!(f, "save state has trailing data"),
+            SaveStateError::InvalidValue { field } => {
+                write!(f, "save state has invalid {}", field)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SaveStateError {}
+
+// numbers are stored big-endian, like CHIP-8 opcodes
+pub(crate) struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub(crate) fn new() -> Self {
+        let mut data = MAGIC.to_vec();
+        data.push(VERSION);
+        StateWriter { data }
+    }
+
+    pub(crate) fn u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub(crate) fn u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn u128(&mut self, value: u128) {
+        self.data.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub(crate) fn bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub(crate) fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    pub(crate) fn new(data: &'a [u8]) -> Result<Self, SaveStateError> {
+        if !data.starts_with(MAGIC) {
+            return Err(SaveStateError::BadMagic);
+        }
+        let mut reader = StateReader {
+            data: &data[MAGIC.len()..],
+        };
+
+        let version = reader.u8()?;
+        if version != VERSION {
+            return Err(SaveStateError::UnsupportedVersion { version });
+        }
+        Ok(reader)
+    }
+
+    pub(crate) fn bytes(&mut self, length: usize) -> Result<&'a [u8], SaveStateError> {
+        if self.data.len() < length {
+            return Err(SaveStateError::Truncated);
+        }
+        let (bytes, rest) = self.data.split_at(length);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    pub(crate) fn u8(&mut self) -> Result<u8, SaveStateError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub(crate) fn u16(&mut self) -> Result<u16, SaveStateError> {
+        let bytes = self.bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub(crate) fn u32(&mut self) -> Result<u32, SaveStateError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, SaveStateError> {
+        Ok(u64::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn u128(&mut self) -> Result<u128, SaveStateError> {
+        Ok(u128::from_be_bytes(self.array()?))
+    }
+
+    pub(crate) fn bool(&mut self, field: &'static str) -> Result<bool, SaveStateError> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(SaveStateError::InvalidValue { field }),
+        }
+    }
+
+    // fills `array` with the next bytes
+    pub(crate) fn array<const N: usize>(&mut self) -> Result<[u8; N], SaveStateError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.bytes(N)?);
+        Ok(array)
+    }
+
+    pub(crate) fn finish(self) -> Result<(), SaveStateError> {
+        if self.data.is_empty() {
+            Ok(())
+        } else {
+            Err(SaveStateError::TrailingData)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_writer_and_reader_round_trip() {
+        let mut writer = StateWriter::new();
+        writer.u8(0xAB);
+        writer.u16(0x1234);
+        writer.u32(0xDEADBEEF);
+        writer.u64(0x0123456789ABCDEF);
+        writer.u128(u128::MAX - 1);
+        writer.bool(true);
+        writer.bytes(&[1, 2, 3]);
+        let data = writer.finish();
+        assert_eq!(b"C8SS", &data[..4]);
+
+        let mut reader = StateReader::new(&data).unwrap();
+        assert_eq!(Ok(0xAB), reader.u8());
+        assert_eq!(Ok(0x1234), reader.u16());
+        assert_eq!(Ok(0xDEADBEEF), reader.u32());
+        assert_eq!(Ok(0x0123456789ABCDEF), reader.u64());
+        assert_eq!(Ok(u128::MAX - 1), reader.u128());
+        assert_eq!(Ok(true), reader.bool("flag"));
+        assert_eq!(Ok([1, 2, 3]), reader.array::<3>());
+        assert_eq!(Ok(()), reader.finish());
+    }
+
+    #[test]
+    fn test_reader_rejects_other_data() {
+        assert_eq!(
+            Err(SaveStateError::BadMagic),
+            StateReader::new(b"\x00\xE0\x12\x00").map(|_| ())
+        );
+        assert_eq!(
+            Err(SaveStateError::UnsupportedVersion { version: 0 }),
+            StateReader::new(b"C8SS\x00").map(|_| ())
+        );
+        assert_eq!(
+            Err(SaveStateError::Truncated),
+            StateReader::new(b"C8SS").map(|_| ())
+        );
+    }
+
+    #[test]
+    fn test_reader_errors() {
+        let mut reader = StateReader::new(b"C8SS\x03\x02\x00").unwrap();
+        assert_eq!(
+            Err(SaveStateError::InvalidValue { field: "flag" }),
+            reader.bool("flag")
+        );
+        assert_eq!(Err(SaveStateError::TrailingData), reader.finish());
+
+        let mut reader = StateReader::new(b"C8SS\x03\x02").unwrap();
+        assert_eq!(Err(SaveStateError::Truncated), reader.u16());
+    }
+}
@@ -0,0 +1,65 @@
+use chip_8_rs::Chip8;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+pub const NUMBER_OF_SLOTS: usize = 4;
+
+// save state slots of a ROM, stored as <rom name>.states/slot<N>.state next to the ROM
+pub struct SaveSlots {
+    directory: PathBuf,
+}
+
+impl SaveSlots {
+    pub fn new(rom: &Path) -> Self {
+        let mut directory = rom.to_path_buf();
+        directory.set_extension("states");
+        SaveSlots { directory }
+    }
+
+    fn path(&self, slot: usize) -> PathBuf {
+        self.directory.join(format!("slot{}.state", slot))
+    }
+
+    pub fn save(&self, slot: usize, chip: &Chip8) -> Result<(), String> {
+        let path = self.path(slot);
+        fs::create_dir_all(&self.directory)
+            .and_then(|_| fs::write(&path, chip.save_state()))
+            .map_err(|error| format!("can't write {}: {}", path.display(), error))
+    }
+
+    pub fn load(&self, slot: usize, chip: &mut Chip8) -> Result<(), String> {
+        let path = self.path(slot);
+        let state =
+            fs::read(&path).map_err(|error| format!("can't read {}: {}", path.display(), error))?;
+        chip.load_state(&state)
+            .map_err(|error| format!("{}: {}", path.display(), error))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip_8_rs::Quirks;
+
+    #[test]
+    fn test_slots_are_stored_next_to_the_rom() {
+        let slots = SaveSlots::new(Path::new("roms/pong.ch8"));
+        assert_eq!(Path::new("roms/pong.states/slot2.state"), slots.path(2));
+    }
+
+    #[test]
+    fn test_save_and_load_slot() {
+        let directory = std::env::temp_dir().join("chip-8-rs-slots-test");
+        let slots = SaveSlots::new(&directory.join("game.ch8"));
+
+        let mut chip = Chip8::new(Quirks::default());
+        chip.load(&[0x6A, 0x42]).unwrap();
+        chip.tick().unwrap();
+        slots.save(1, &chip).unwrap();
+
+        let mut restored = Chip8::new(Quirks::default());
+        slots.load(1, &mut restored).unwrap();
+        assert_eq!(0x42, restored.get_registers()[0xA]);
+        assert!(slots.load(3, &mut restored).is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use clap::ValueEnum;
+
+// how the display is scaled to the window, the rest of the window is left black
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub enum Fit {
+    // pixels are a whole number of window pixels, so they all have the same size
+    Integer,
+    // as large as fits, pixels may differ by one window pixel
+    Aspect,
+}
+
+// where the display goes in the window, recalculated every frame because the window can be
+// resized and the resolution changes when a program switches to hi-res
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    pub fn new(fit: Fit, window: (i32, i32), display: (usize, usize)) -> Self {
+        let (window_width, window_height) = (window.0 as f32, window.1 as f32);
+        let (width, height) = (display.0 as f32, display.1 as f32);
+        let largest = f32::min(window_width / width, window_height / height);
+        let pixel_size = match fit {
+            // a window smaller than the display gets scaled down anyway
+            Fit::Integer if largest >= 1.0 => largest.floor(),
+            _ => largest,
+        };
+        Viewport {
+            x: ((window_width - width * pixel_size) / 2.0).floor(),
+            y: ((window_height - height * pixel_size) / 2.0).floor(),
+            width: width * pixel_size,
+            height: height * pixel_size,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_integer_fit() {
+        let viewport = Viewport::new(Fit::Integer, (700, 400), (64, 32));
+        assert_eq!(
+            Viewport {
+                x: 30.0,
+                y: 40.0,
+                width: 640.0,
+                height: 320.0
+            },
+            viewport
+        );
+
+        // hi-res in the same window
+        let viewport = Viewport::new(Fit::Integer, (700, 400), (128, 64));
+        assert_eq!((30.0, 640.0), (viewport.x, viewport.width));
+    }
+
+    #[test]
+    fn test_aspect_fit() {
+        let viewport = Viewport::new(Fit::Aspect, (100, 100), (64, 32));
+        assert_eq!(
+            Viewport {
+                x: 0.0,
+                y: 25.0,
+                width: 100.0,
+                height: 50.0
+            },
+            viewport
+        );
+    }
+
+    #[test]
+    fn test_window_smaller_than_display() {
+        // scaled down to fill the window
+        let viewport = Viewport::new(Fit::Integer, (64, 32), (128, 64));
+        assert_eq!(
+            (0.0, 0.0, 64.0, 32.0),
+            (viewport.x, viewport.y, viewport.width, viewport.height)
+        );
+    }
+}
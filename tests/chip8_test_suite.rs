@@ -0,0 +1,165 @@
+// runs the ROMs of https://github.com/Timendus/chip8-test-suite and compares the final screen
+// against the golden snapshots in tests/snapshots
+// ROMs go in tests/roms/chip8-test-suite, a missing ROM or snapshot fails the test
+// the tests are ignored until the ROMs are committed, run them with `-- --ignored`
+// run with UPDATE_SNAPSHOTS=1 to write the snapshots after checking the screens by hand
+use chip_8_rs::{chip8, Chip8, Quirks};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// the menus of the test ROMs read the option to run from this address, 0 shows the menu
+const MODE_ADDRESS: usize = 0x1FF;
+
+struct Case {
+    // name of the snapshot file
+    name: &'static str,
+    rom: &'static str,
+    quirks: Quirks,
+    mode: Option<u8>,
+    frames: u64,
+    // (key, first frame, frames) the key is held down
+    keys: &'static [(usize, u64, u64)],
+}
+
+impl Case {
+    fn new(name: &'static str, rom: &'static str, quirks: Quirks) -> Self {
+        Case {
+            name,
+            rom,
+            quirks,
+            mode: None,
+            frames: 120,
+            keys: &[],
+        }
+    }
+}
+
+fn directory(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests")
+        .join(name)
+}
+
+// '.' for an unset pixel, '#' for a set one, like the headless runner
+fn screen_to_ascii(chip: &Chip8) -> String {
+    let mut ascii = String::new();
+    for row in chip.get_vram().chunks(chip.width()) {
+        ascii.extend(row.iter().map(|pixel| if *pixel == 0 { '.' } else { '#' }));
+        ascii.push('\n');
+    }
+    ascii
+}
+
+fn run(case: &Case, rom: &[u8]) -> String {
+    let mut chip = Chip8::new(case.quirks);
+    chip.load(rom).unwrap();
+    if let Some(mode) = case.mode {
+        chip.set_ram(MODE_ADDRESS, mode).unwrap();
+    }
+
+    for frame in 0..case.frames {
+        for key in 0..chip8::NUMBER_OF_KEYS {
+            let down = case.keys.iter().any(|(pressed, first_frame, frames)| {
+                *pressed == key && (*first_frame..first_frame + frames).contains(&frame)
+            });
+            chip.set_key(key, down).unwrap();
+        }
+        if let Err(error) = chip.run_frame() {
+            panic!("{}: {}", case.name, error);
+        }
+    }
+
+    screen_to_ascii(&chip)
+}
+
+fn check(cases: &[Case]) {
+    for case in cases {
+        let rom_path = directory("roms/chip8-test-suite").join(case.rom);
+        let rom = fs::read(&rom_path).unwrap_or_else(|error| {
+            panic!(
+                "{}: can't read {}: {}, see tests/roms/chip8-test-suite/README.md",
+                case.name,
+                rom_path.display(),
+                error
+            )
+        });
+
+        let screen = run(case, &rom);
+        let snapshot_path = directory("snapshots").join(format!("{}.txt", case.name));
+        if std::env::var_os("UPDATE_SNAPSHOTS").is_some() {
+            fs::create_dir_all(snapshot_path.parent().unwrap()).unwrap();
+            fs::write(&snapshot_path, &screen).unwrap();
+            continue;
+        }
+
+        let expected = fs::read_to_string(&snapshot_path).unwrap_or_else(|error| {
+            panic!(
+                "{}: can't read {}: {}, run with UPDATE_SNAPSHOTS=1 to create it",
+                case.name,
+                snapshot_path.display(),
+                error
+            )
+        });
+        let different = screen
+            .chars()
+            .zip(expected.chars())
+            .filter(|(pixel, expected)| pixel != expected)
+            .count();
+        assert!(
+            screen == expected,
+            "{}: {} pixels differ from {}\nexpected:\n{}\nactual:\n{}",
+            case.name,
+            different,
+            snapshot_path.display(),
+            expected,
+            screen
+        );
+    }
+}
+
+#[test]
+#[ignore = "needs the chip8-test-suite ROMs in tests/roms/chip8-test-suite"]
+fn test_corax_plus() {
+    check(&[Case::new("corax+", "3-corax+.ch8", Quirks::COSMAC_VIP)]);
+}
+
+#[test]
+#[ignore = "needs the chip8-test-suite ROMs in tests/roms/chip8-test-suite"]
+fn test_flags() {
+    check(&[Case::new("flags", "4-flags.ch8", Quirks::COSMAC_VIP)]);
+}
+
+#[test]
+#[ignore = "needs the chip8-test-suite ROMs in tests/roms/chip8-test-suite"]
+fn test_quirks() {
+    let case = |name, quirks, mode| Case {
+        mode: Some(mode),
+        // Dxyn waits for the vertical blank, so the CHIP-8 run takes a while
+        frames: 600,
+        ..Case::new(name, "5-quirks.ch8", quirks)
+    };
+    check(&[
+        case("quirks-chip-8", Quirks::COSMAC_VIP, 1),
+        case("quirks-super-chip", Quirks::SUPER_CHIP, 2),
+        case("quirks-xo-chip", Quirks::XO_CHIP, 3),
+    ]);
+}
+
+#[test]
+#[ignore = "needs the chip8-test-suite ROMs in tests/roms/chip8-test-suite"]
+fn test_keypad() {
+    check(&[
+        // Ex9E/ExA1, key 5 is held down
+        Case {
+            mode: Some(1),
+            keys: &[(5, 30, 60)],
+            ..Case::new("keypad-ex9e-exa1", "6-keypad.ch8", Quirks::COSMAC_VIP)
+        },
+        // Fx0A, key 5 is pressed and released
+        Case {
+            mode: Some(3),
+            keys: &[(5, 30, 10)],
+            ..Case::new("keypad-fx0a", "6-keypad.ch8", Quirks::COSMAC_VIP)
+        },
+    ]);
+}